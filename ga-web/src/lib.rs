@@ -7,8 +7,9 @@ use generative_art::{
         Color, LineEnd, Stroke, UVec2,
     },
     sketchers::{
-        CelestialSketcher, CelestialSketcherSettings, HalftoneSketcher, HalftoneSketcherSettings,
-        PreslavSketcher, PreslavSketcherSettings, Sketcher, WaveSketcher, WaveSketcherSettings,
+        CelestialSketcher, CelestialSketcherSettings, HalftoneSeparation, HalftoneSketcher,
+        HalftoneSketcherSettings, PreslavPlacement, PreslavShape, PreslavSketcher,
+        PreslavSketcherSettings, Sketcher, WavePhase, WaveSketcher, WaveSketcherSettings, Waveform,
     },
     RasterCanvas, VectorCanvas, VectorizerStyle,
 };
@@ -67,10 +68,11 @@ pub fn celestial(
         object_size: Uniform::new_inclusive(min_object_size, max_object_size),
         object_velocity: Uniform::new_inclusive(0.0, 0.0),
         g,
-        foreground: Color::white(),
+        foreground: Color::white().into(),
         steps,
         step_length,
         render_polygon: None,
+        layered: false,
     };
 
     let sketcher = CelestialSketcher::new(settings, seed as u64);
@@ -110,6 +112,7 @@ pub fn waves(
             width: stroke_width,
             line_end: LineEnd::Round,
         },
+        stroke_colors: None,
         skip_rows,
         skip_columns,
         frequency_multiplier,
@@ -118,6 +121,9 @@ pub fn waves(
         brightness_threshold,
         box_blur_radius,
         stroke_with_frequency,
+        waveform: Waveform::Sine,
+        phase: WavePhase::Zero,
+        angle: 0.0,
     };
 
     let image = unsafe { LOADED_IMAGE.clone().unwrap() };
@@ -127,7 +133,7 @@ pub fn waves(
         3000,
     );
 
-    let sketcher = WaveSketcher::new(image, settings);
+    let sketcher = WaveSketcher::new(image, settings, 0);
 
     let canvas = sketcher
         .run_and_dispose(|_| ())
@@ -166,6 +172,11 @@ pub fn preslav(
         stroke_reduction,
         randomize_rotation,
         shapes,
+        shape: PreslavShape::RegularPolygon,
+        follow_edges: false,
+        detail_sizing: 0.0,
+        edge_blur_radius: 0,
+        placement: PreslavPlacement::Uniform,
     };
 
     let image = unsafe { LOADED_IMAGE.clone().unwrap() };
@@ -196,7 +207,8 @@ pub fn halftone(
         dot_density,
         dot_scale,
         dot_sides: dot_sides as usize,
-        dot_color: Color::from_hex(&dot_color).unwrap(),
+        dot_color: Color::from_hex(&dot_color).unwrap().into(),
+        separation: HalftoneSeparation::Value,
     };
 
     let image = unsafe { LOADED_IMAGE.clone().unwrap() };
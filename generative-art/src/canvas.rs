@@ -1,9 +1,16 @@
+use std::fmt::Write;
 use std::{fs, io, path::PathBuf};
 
+use crate::helpers::{polygon_spans, PixelTransform};
+use crate::palette::{ColorMap, Palette, PaletteExtraction};
+use crate::plotter::{PlotterFormat, PlotterSettings};
+
 use barium::{
     renderers::{SkiaRenderer, SvgRenderer},
-    Color, RgbaImage, UVec2, Vec2,
+    Color, LineEnd, RgbaImage, UVec2, Vec2,
 };
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 /// Which algorithm to use when vectorizing a [RasterCanvas].
 pub enum VectorizerStyle {
@@ -15,6 +22,7 @@ pub enum VectorizerStyle {
 pub enum OmniCanvas {
     VectorCanvas { inner: VectorCanvas },
     RasterCanvas { inner: RasterCanvas },
+    LayeredCanvas { inner: LayeredCanvas },
 }
 
 impl OmniCanvas {
@@ -37,7 +45,7 @@ impl OmniCanvas {
         resolution: UVec2,
         anti_alias: bool,
         background_color: Option<Color>,
-        preserve_height: bool
+        preserve_height: bool,
     ) -> RasterCanvas {
         self.clone()
             .into_raster_canvas(resolution, anti_alias, background_color, preserve_height)
@@ -51,7 +59,8 @@ impl OmniCanvas {
     pub fn into_vector_canvas(self, style: VectorizerStyle) -> VectorCanvas {
         match self {
             OmniCanvas::VectorCanvas { inner } => inner,
-            OmniCanvas::RasterCanvas { mut inner } => {
+            OmniCanvas::LayeredCanvas { inner } => inner.into_flattened(),
+            OmniCanvas::RasterCanvas { inner } => {
                 let mut vector = VectorCanvas::default();
                 match style {
                     VectorizerStyle::Pixels => {
@@ -87,15 +96,25 @@ impl OmniCanvas {
         preserve_height: bool,
     ) -> RasterCanvas {
         match self {
-            OmniCanvas::VectorCanvas { inner } => {
-                RasterCanvas::from_rgba(&inner.render(SkiaRenderer::new(
-                    resolution,
-                    background_color,
-                    antialias,
-                    preserve_height,
-                )))
-            }
+            OmniCanvas::VectorCanvas { inner } => RasterCanvas::from_rgba(&inner.render(
+                SkiaRenderer::new(resolution, background_color, antialias, preserve_height),
+            )),
             OmniCanvas::RasterCanvas { inner } => inner,
+            OmniCanvas::LayeredCanvas { inner } => OmniCanvas::from(inner.into_flattened())
+                .into_raster_canvas(resolution, antialias, background_color, preserve_height),
+        }
+    }
+
+    /// Replaces every color with the closest color of a palette. See [RasterCanvas::remap_to_palette] and [Palette::remap_vector].
+    pub fn remap_to_palette(&mut self, palette: &Palette) {
+        match self {
+            OmniCanvas::VectorCanvas { inner } => palette.remap_vector(inner),
+            OmniCanvas::RasterCanvas { inner } => inner.remap_to_palette(palette),
+            OmniCanvas::LayeredCanvas { inner } => {
+                for (_, layer) in inner.layers_mut() {
+                    palette.remap_vector(layer);
+                }
+            }
         }
     }
 
@@ -107,12 +126,14 @@ impl OmniCanvas {
     /// * Tiff
     /// * Bmp
     /// * Svg
+    ///
+    /// A [LayeredCanvas] is saved to SVG with a group for each layer, which Inkscape opens as layers.
     pub fn save<T: Into<PathBuf>>(
         &self,
         path: T,
         size: Vec2,
         background_color: Option<Color>,
-        preserve_height: bool
+        preserve_height: bool,
     ) -> io::Result<()> {
         let path = path.into();
 
@@ -127,6 +148,12 @@ impl OmniCanvas {
                 }
             }
             Some("svg") => {
+                if let OmniCanvas::LayeredCanvas { inner } = self {
+                    fs::write(path, inner.to_svg(size, background_color, preserve_height))?;
+
+                    return Ok(());
+                }
+
                 fs::write(
                     path,
                     self.as_vector_canvas(VectorizerStyle::Pixels)
@@ -149,6 +176,72 @@ impl OmniCanvas {
 
         Ok(())
     }
+
+    /// Save the OmniCanvas as instructions for a pen plotter. A [RasterCanvas] is vectorized first.
+    ///
+    /// If `per_color` is set, each color is saved to its own file, numbered in the order the colors first appear
+    /// (`plot.gcode` is saved as `plot-1.gcode`, `plot-2.gcode` and so on).
+    /// A [LayeredCanvas] is split by layer instead, with each file named after its layer (like `plot-cyan.gcode`).
+    pub fn save_plot<T: Into<PathBuf>>(
+        &self,
+        path: T,
+        settings: &PlotterSettings,
+        format: &PlotterFormat,
+        per_color: bool,
+    ) -> io::Result<()> {
+        let path = path.into();
+        let canvas = self.as_vector_canvas(VectorizerStyle::Pixels);
+
+        if !per_color {
+            return fs::write(path, settings.export(&canvas, format));
+        }
+
+        let plots: Vec<(String, String)> = match self {
+            OmniCanvas::LayeredCanvas { inner } => settings.export_layers(inner, format),
+            _ => settings
+                .export_per_color(&canvas, format)
+                .into_iter()
+                .enumerate()
+                .map(|(i, (_, plot))| ((i + 1).to_string(), plot))
+                .collect(),
+        };
+
+        for (name, plot) in plots {
+            fs::write(suffixed_path(&path, &name, format.extension()), plot)?;
+        }
+
+        Ok(())
+    }
+
+    /// Save each layer of the OmniCanvas to its own file, named after the layer (`art.svg` is saved as `art-cyan.svg` and so on).
+    /// Canvases without layers are saved to a single file, as with [save](OmniCanvas::save).
+    pub fn save_layers<T: Into<PathBuf>>(
+        &self,
+        path: T,
+        size: Vec2,
+        background_color: Option<Color>,
+        preserve_height: bool,
+    ) -> io::Result<()> {
+        let path = path.into();
+
+        let OmniCanvas::LayeredCanvas { inner } = self else {
+            return self.save(path, size, background_color, preserve_height);
+        };
+
+        for (name, layer) in inner.layers() {
+            let mut single = LayeredCanvas::new();
+            *single.layer_mut(name) = layer.clone();
+
+            OmniCanvas::from(single).save(
+                suffixed_path(&path, name, "svg"),
+                size,
+                background_color,
+                preserve_height,
+            )?;
+        }
+
+        Ok(())
+    }
 }
 
 impl From<VectorCanvas> for OmniCanvas {
@@ -157,6 +250,12 @@ impl From<VectorCanvas> for OmniCanvas {
     }
 }
 
+impl From<LayeredCanvas> for OmniCanvas {
+    fn from(canvas: LayeredCanvas) -> Self {
+        Self::LayeredCanvas { inner: canvas }
+    }
+}
+
 impl From<RasterCanvas> for OmniCanvas {
     #[inline]
     fn from(canvas: RasterCanvas) -> Self {
@@ -164,8 +263,247 @@ impl From<RasterCanvas> for OmniCanvas {
     }
 }
 
+/// Adds a suffix to the name of a file, before its extension (or the default extension, if it has none).
+fn suffixed_path(path: &std::path::Path, suffix: &str, default_extension: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or(default_extension);
+
+    path.with_file_name(format!("{}-{}.{}", stem, suffix, extension))
+}
+
+/// Blends one color over another, using the alpha of both.
+pub(crate) fn blend(under: Color, over: Color) -> Color {
+    let alpha = over.a() + under.a() * (1.0 - over.a());
+
+    if alpha <= 0.0 {
+        Color::transparent()
+    } else {
+        let mix = |over_channel: f32, under_channel: f32| {
+            (over_channel * over.a() + under_channel * under.a() * (1.0 - over.a())) / alpha
+        };
+
+        Color::new(
+            mix(over.r(), under.r()),
+            mix(over.g(), under.g()),
+            mix(over.b(), under.b()),
+            alpha,
+        )
+    }
+}
+
 pub type VectorCanvas = barium::Canvas;
 
+/// A [VectorCanvas] split into named layers, such as one for each pen of a plotter or each ink of a print.
+/// Layers are drawn in the order they were created, so later layers cover earlier ones.
+#[derive(Clone, Default)]
+pub struct LayeredCanvas {
+    layers: Vec<(String, VectorCanvas)>,
+}
+
+impl LayeredCanvas {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets a layer by name, adding an empty one on top of the others if there is none.
+    pub fn layer_mut(&mut self, name: &str) -> &mut VectorCanvas {
+        let index = self.layer_index(name);
+
+        &mut self.layers[index].1
+    }
+
+    /// Gets the index of a layer in [layers](Self::layers) by name, adding an empty one on top of the others if there is none.
+    /// Looking a layer up once and drawing into it by index avoids searching the layers for every shape.
+    pub fn layer_index(&mut self, name: &str) -> usize {
+        match self.layers.iter().position(|(layer, _)| layer == name) {
+            Some(index) => index,
+            None => {
+                self.layers
+                    .push((name.to_string(), VectorCanvas::default()));
+                self.layers.len() - 1
+            }
+        }
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&VectorCanvas> {
+        self.layers
+            .iter()
+            .find(|(layer, _)| layer == name)
+            .map(|(_, canvas)| canvas)
+    }
+
+    /// The names and contents of the layers, from the bottom to the top.
+    pub fn layers(&self) -> &[(String, VectorCanvas)] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [(String, VectorCanvas)] {
+        &mut self.layers
+    }
+
+    /// Merges the layers into a single [VectorCanvas], keeping the order they are drawn in.
+    pub fn flatten(&self) -> VectorCanvas {
+        self.clone().into_flattened()
+    }
+
+    /// Consumes the canvas and merges its layers into a single [VectorCanvas], keeping the order they are drawn in.
+    pub fn into_flattened(self) -> VectorCanvas {
+        let mut flattened = VectorCanvas::default();
+
+        for (_, mut layer) in self.layers {
+            flattened.as_raw_mut().append(layer.as_raw_mut());
+        }
+
+        flattened
+    }
+
+    /// Renders the canvas to an SVG document, with each layer in a group that Inkscape recognizes as a layer.
+    ///
+    /// This is written by hand, since [SvgRenderer] renders a single canvas into a whole document, but it follows
+    /// the same conventions, so that a layered canvas saves the same as its [flattened](Self::flatten) one:
+    ///
+    /// * The canvas is centered on the image, with its y axis flipped to point down.
+    /// * If `preserve_height` is set, `-1..1` spans the height of the image, otherwise its width.
+    /// * Stroke widths are scaled by the same amount as the points.
+    /// * [LineEnd::Round] strokes get round caps and any other line end gets butt caps. Joins are always round.
+    /// * Fills close their paths, and colors are written as hex with a separate opacity.
+    pub fn to_svg(
+        &self,
+        size: Vec2,
+        background_color: Option<Color>,
+        preserve_height: bool,
+    ) -> String {
+        let scale = if preserve_height { size.y } else { size.x } / 2.0;
+        let to_image = |p: Vec2| Vec2::new(p.x * scale + size.x / 2.0, size.y / 2.0 - p.y * scale);
+
+        let paint = |color: Color| {
+            let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+            format!(
+                "#{:02x}{:02x}{:02x}",
+                channel(color.r()),
+                channel(color.g()),
+                channel(color.b())
+            )
+        };
+
+        let mut svg = String::new();
+
+        // Writing to a string can't fail.
+        writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = size.x,
+            h = size.y
+        )
+        .unwrap();
+
+        if let Some(background) = background_color {
+            writeln!(
+                svg,
+                r#"<rect width="100%" height="100%" fill="{}" fill-opacity="{}"/>"#,
+                paint(background),
+                background.a()
+            )
+            .unwrap();
+        }
+
+        for (i, (name, layer)) in self.layers.iter().enumerate() {
+            writeln!(
+                svg,
+                r#"<g inkscape:groupmode="layer" inkscape:label="{}" id="layer{}">"#,
+                escape_xml(name),
+                i + 1
+            )
+            .unwrap();
+
+            for shape in layer
+                .as_raw()
+                .iter()
+                .filter(|shape| !shape.points.is_empty())
+            {
+                let mut path = String::new();
+
+                for (j, p) in shape.points.iter().enumerate() {
+                    let p = to_image(*p);
+
+                    write!(
+                        path,
+                        "{}{:.3} {:.3} ",
+                        if j == 0 { 'M' } else { 'L' },
+                        p.x,
+                        p.y
+                    )
+                    .unwrap();
+                }
+
+                write!(svg, r#"<path d="{}"#, path.trim_end()).unwrap();
+
+                match shape.fill {
+                    Some(fill) => write!(
+                        svg,
+                        r#" Z" fill="{}" fill-opacity="{}""#,
+                        paint(fill),
+                        fill.a()
+                    )
+                    .unwrap(),
+                    None => write!(svg, r#"" fill="none""#).unwrap(),
+                }
+
+                if let Some(stroke) = shape.stroke {
+                    let line_end = match stroke.line_end {
+                        LineEnd::Round => "round",
+                        _ => "butt",
+                    };
+
+                    write!(
+                        svg,
+                        r#" stroke="{}" stroke-opacity="{}" stroke-width="{}" stroke-linecap="{}" stroke-linejoin="round""#,
+                        paint(stroke.color),
+                        stroke.color.a(),
+                        stroke.width * scale,
+                        line_end
+                    )
+                    .unwrap();
+                }
+
+                writeln!(svg, "/>").unwrap();
+            }
+
+            writeln!(svg, "</g>").unwrap();
+        }
+
+        writeln!(svg, "</svg>").unwrap();
+
+        svg
+    }
+}
+
+impl From<VectorCanvas> for LayeredCanvas {
+    /// Puts the whole canvas into a single layer.
+    fn from(canvas: VectorCanvas) -> Self {
+        let mut layered = Self::new();
+        *layered.layer_mut("Layer 1") = canvas;
+
+        layered
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
 #[derive(Clone)]
 pub struct RasterCanvas {
     width: usize,
@@ -216,7 +554,7 @@ impl RasterCanvas {
     }
 
     /// Gets a pixel from the canvas. If the requested pixel is out of range, it will return [Color::black()]
-    pub fn get_pixel(&mut self, x: usize, y: usize) -> Color {
+    pub fn get_pixel(&self, x: usize, y: usize) -> Color {
         if x >= self.width() || y >= self.height() {
             return Color::black();
         }
@@ -224,6 +562,141 @@ impl RasterCanvas {
         self.image[y * self.width + x]
     }
 
+    /// Gets the brightness of a pixel, premultiplied by its alpha.
+    /// If the requested pixel is out of range, it will return `0.0`.
+    pub fn get_brightness(&self, x: usize, y: usize) -> f32 {
+        let pixel = self.get_pixel(x, y);
+
+        (pixel.r() + pixel.g() + pixel.b()) * pixel.a() / 3.0
+    }
+
+    /// Gets the average brightness of the pixels within a box of the given radius around a pixel.
+    /// Pixels outside of the canvas are ignored. For no blur, set the radius to zero.
+    pub fn get_blurred_brightness(&self, x: usize, y: usize, radius: usize) -> f32 {
+        if radius == 0 {
+            return self.get_brightness(x, y);
+        }
+
+        let min_x = x.saturating_sub(radius);
+        let max_x = usize::min(x + radius, self.width.saturating_sub(1));
+        let min_y = y.saturating_sub(radius);
+        let max_y = usize::min(y + radius, self.height.saturating_sub(1));
+
+        let mut total = 0.0;
+        let mut count = 0;
+
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                total += self.get_brightness(x, y);
+                count += 1;
+            }
+        }
+
+        if count == 0 {
+            0.0
+        } else {
+            total / count as f32
+        }
+    }
+
+    /// Gets the gradient of the brightness around a pixel, using a Sobel operator over box-blurred brightness.
+    /// It points from dark areas towards light areas, and its length is the change in brightness per pixel.
+    /// Pixels outside of the canvas are clamped to its edges.
+    pub fn get_gradient(&self, x: usize, y: usize, radius: usize) -> Vec2 {
+        if self.width == 0 || self.height == 0 {
+            return Vec2::ZERO;
+        }
+
+        let step = radius.max(1);
+
+        let left = x.saturating_sub(step);
+        let right = usize::min(x + step, self.width - 1);
+        let top = y.saturating_sub(step);
+        let bottom = usize::min(y + step, self.height - 1);
+        let x = usize::min(x, self.width - 1);
+        let y = usize::min(y, self.height - 1);
+
+        let b = |x, y| self.get_blurred_brightness(x, y, radius);
+
+        let gx = (b(right, top) + 2.0 * b(right, y) + b(right, bottom))
+            - (b(left, top) + 2.0 * b(left, y) + b(left, bottom));
+        let gy = (b(left, bottom) + 2.0 * b(x, bottom) + b(right, bottom))
+            - (b(left, top) + 2.0 * b(x, top) + b(right, top));
+
+        Vec2::new(gx, gy) / (8.0 * step as f32)
+    }
+
+    /// Blends a color over a pixel of the canvas, using its alpha. Pixels out of range are ignored.
+    pub fn blend_pixel(&mut self, x: usize, y: usize, color: Color) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        let under = self.image[y * self.width + x];
+
+        self.image[y * self.width + x] = blend(under, color);
+    }
+
+    /// Fills a polygon (with the even-odd rule), blending it over the canvas.
+    /// The points are in pixel space, and pixels are covered if their centers are inside the polygon.
+    pub fn fill_polygon(&mut self, points: &[Vec2], color: Color) {
+        for (y, columns) in polygon_spans(points, self.width, self.height) {
+            for x in columns {
+                self.blend_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// Gets the average color of the pixels covered by a polygon (in pixel space).
+    /// Returns [None] if the polygon does not cover the center of any pixel.
+    pub fn get_average_color(&self, points: &[Vec2]) -> Option<Color> {
+        let mut sum = [0.0; 4];
+        let mut count = 0;
+
+        for (y, columns) in polygon_spans(points, self.width, self.height) {
+            for x in columns {
+                let pixel = self.get_pixel(x, y);
+
+                sum[0] += pixel.r();
+                sum[1] += pixel.g();
+                sum[2] += pixel.b();
+                sum[3] += pixel.a();
+                count += 1;
+            }
+        }
+
+        if count == 0 {
+            return None;
+        }
+
+        let count = count as f32;
+
+        Some(Color::new(
+            sum[0] / count,
+            sum[1] / count,
+            sum[2] / count,
+            sum[3] / count,
+        ))
+    }
+
+    /// Extracts a palette of up to `count` colors, weighted by how much of the image each covers.
+    /// Transparent pixels are ignored.
+    pub fn extract_palette(&self, count: usize, method: PaletteExtraction) -> Palette {
+        Palette::extract(self.image.iter().copied(), count, method)
+    }
+
+    /// Replaces every pixel with the closest color of a palette, keeping its alpha.
+    pub fn remap_to_palette(&mut self, palette: &Palette) {
+        #[cfg(feature = "parallel")]
+        self.image
+            .par_iter_mut()
+            .for_each(|pixel| *pixel = palette.nearest(*pixel));
+        #[cfg(not(feature = "parallel"))]
+        self.image
+            .iter_mut()
+            .for_each(|pixel| *pixel = palette.nearest(*pixel));
+    }
+
     pub fn get_raw(&self) -> &[Color] {
         self.image.as_slice()
     }
@@ -246,3 +719,189 @@ impl From<RgbaImage> for RasterCanvas {
         RasterCanvas::from_rgba(&image)
     }
 }
+
+/// How a [DensityCanvas] maps hit counts to colors.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ToneMapping {
+    /// Scale densities logarithmically, so that faint detail stays visible next to dense areas.
+    pub log: bool,
+    /// Brightens (above `1.0`) or darkens (below `1.0`) the mapped densities.
+    pub gamma: f32,
+}
+
+impl ToneMapping {
+    /// Maps a density to the range `0.0` to `1.0`, given the highest density it is compared against.
+    pub fn apply(&self, density: f32, max: f32) -> f32 {
+        let max = max.max(f32::EPSILON);
+
+        let t = if self.log {
+            density.ln_1p() / max.ln_1p()
+        } else {
+            density / max
+        };
+
+        t.clamp(0.0, 1.0).powf(1.0 / self.gamma.max(f32::EPSILON))
+    }
+}
+
+/// An accumulation buffer, which counts how many times points land in each pixel.
+///
+/// Points are in canvas space, where the minor dimension of the buffer spans -1..1.
+/// Drawing millions of points this way is far cheaper than drawing them as shapes on a [VectorCanvas].
+#[derive(Clone)]
+pub struct DensityCanvas {
+    width: usize,
+    height: usize,
+    transform: PixelTransform,
+    density: Vec<f32>,
+}
+
+impl DensityCanvas {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            transform: PixelTransform::new(width, height),
+            density: vec![0.0; width * height],
+        }
+    }
+
+    /// Adds a hit with a weight at a point. Points outside of the buffer are ignored.
+    pub fn add(&mut self, p: Vec2, weight: f32) {
+        let p = self.transform.invert(p);
+
+        if p.x >= 0.0 && p.y >= 0.0 && p.x < self.width as f32 && p.y < self.height as f32 {
+            self.density[p.y as usize * self.width + p.x as usize] += weight;
+        }
+    }
+
+    /// Adds the hits of another buffer of the same size to this one.
+    pub fn merge(&mut self, other: &DensityCanvas) {
+        for (a, b) in self.density.iter_mut().zip(&other.density) {
+            *a += b;
+        }
+    }
+
+    /// Gets the total weight of the hits in a pixel. If the requested pixel is out of range, it will return `0.0`.
+    pub fn get_density(&self, x: usize, y: usize) -> f32 {
+        if x >= self.width || y >= self.height {
+            return 0.0;
+        }
+
+        self.density[y * self.width + x]
+    }
+
+    /// Gets the highest density of any pixel.
+    pub fn max_density(&self) -> f32 {
+        self.density.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Maps the density of each pixel to the range `0.0` to `1.0`, row by row.
+    pub fn tone_map(&self, tone_mapping: ToneMapping) -> Vec<f32> {
+        let max = self.max_density();
+
+        self.density
+            .iter()
+            .map(|&density| tone_mapping.apply(density, max))
+            .collect()
+    }
+
+    /// Renders the buffer by tone mapping each pixel and mapping it to a color, from empty pixels (`0.0`) to the densest ones (`1.0`).
+    pub fn to_raster(&self, tone_mapping: ToneMapping, colormap: &ColorMap) -> RasterCanvas {
+        RasterCanvas {
+            width: self.width,
+            height: self.height,
+            image: self
+                .tone_map(tone_mapping)
+                .into_iter()
+                .map(|t| colormap.sample(t))
+                .collect(),
+        }
+    }
+
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use barium::{Color, LineEnd, Stroke, Vec2};
+
+    use super::LayeredCanvas;
+
+    fn stroke(color: Color, line_end: LineEnd) -> Option<Stroke> {
+        Some(Stroke {
+            color,
+            width: 0.1,
+            line_end,
+        })
+    }
+
+    /// The path elements of an SVG document, without the groups around them.
+    fn paths(svg: &str) -> Vec<&str> {
+        svg.lines()
+            .filter(|line| line.starts_with("<path"))
+            .collect()
+    }
+
+    #[test]
+    fn svg_follows_renderer_conventions() {
+        let mut canvas = LayeredCanvas::new();
+        canvas.layer_mut("a").draw_shape(
+            vec![Vec2::new(-1.0, 0.5), Vec2::new(1.0, -0.5)],
+            stroke(Color::black(), LineEnd::Round),
+            None,
+        );
+        canvas.layer_mut("b").draw_shape(
+            vec![Vec2::ZERO, Vec2::new(0.5, 0.0), Vec2::new(0.0, 0.5)],
+            stroke(Color::white(), LineEnd::Butt),
+            Some(Color::black()),
+        );
+
+        // Scaled by half the width, with y pointing down from the center.
+        let svg = canvas.to_svg(Vec2::new(200.0, 100.0), None, false);
+        assert_eq!(
+            paths(&svg),
+            [
+                r##"<path d="M0.000 0.000 L200.000 100.000" fill="none" stroke="#000000" stroke-opacity="1" stroke-width="10" stroke-linecap="round" stroke-linejoin="round"/>"##,
+                r##"<path d="M100.000 50.000 L150.000 50.000 L100.000 0.000 Z" fill="#000000" fill-opacity="1" stroke="#ffffff" stroke-opacity="1" stroke-width="10" stroke-linecap="butt" stroke-linejoin="round"/>"##,
+            ]
+        );
+
+        // Scaled by half the height instead.
+        let svg = canvas.to_svg(Vec2::new(200.0, 100.0), None, true);
+        assert!(
+            paths(&svg)[0].starts_with(r#"<path d="M50.000 25.000 L150.000 75.000" fill="none""#)
+        );
+        assert!(paths(&svg)[0].contains(r#"stroke-width="5""#));
+    }
+
+    #[test]
+    fn layers_render_like_their_flattened_canvas() {
+        let mut canvas = LayeredCanvas::new();
+        for (i, name) in ["cyan", "magenta", "cyan", "yellow"].iter().enumerate() {
+            let x = i as f32 / 4.0;
+            canvas.layer_mut(name).draw_shape(
+                vec![Vec2::new(x, -x), Vec2::new(-x, x)],
+                stroke(Color::black(), LineEnd::Round),
+                None,
+            );
+        }
+
+        let flattened = LayeredCanvas::from(canvas.flatten());
+
+        for preserve_height in [false, true] {
+            let size = Vec2::new(300.0, 200.0);
+
+            assert_eq!(
+                paths(&canvas.to_svg(size, Some(Color::white()), preserve_height)),
+                paths(&flattened.to_svg(size, Some(Color::white()), preserve_height))
+            );
+        }
+    }
+}
@@ -1,3 +1,9 @@
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+
+use barium::{Mat2, Vec2};
+
+use crate::canvas::RasterCanvas;
 use rand::{Rng, RngCore};
 
 pub trait RngCoreExt {
@@ -16,3 +22,594 @@ where
         }
     }
 }
+
+/// Transforms points from the pixel space of an input image to canvas space.
+///
+/// The center of the image is placed at the origin, and the minor dimension of the image spans -1..1.
+#[derive(Clone, Copy)]
+pub(crate) struct PixelTransform {
+    half_size: Vec2,
+    scale_mat: Mat2,
+}
+
+impl PixelTransform {
+    pub fn new(width: usize, height: usize) -> Self {
+        let major_dimension = usize::min(width, height) as f32;
+
+        Self {
+            half_size: Vec2::new(width as f32, height as f32) / 2.0,
+            scale_mat: Mat2::from_cols(
+                Vec2::X * (2.0 / major_dimension),
+                Vec2::Y * (-2.0 / major_dimension),
+            ),
+        }
+    }
+
+    /// Transforms a point from pixel space to canvas space.
+    pub fn apply(&self, p: Vec2) -> Vec2 {
+        self.scale_mat.mul_vec2(p - self.half_size)
+    }
+
+    /// Transforms a point from canvas space back to pixel space.
+    pub fn invert(&self, p: Vec2) -> Vec2 {
+        self.scale_mat.inverse().mul_vec2(p) + self.half_size
+    }
+}
+
+/// How far a miter join may extend, relative to the half-width of the line, before it is clipped.
+const MITER_LIMIT: f32 = 4.0;
+
+/// The number of segments used to approximate each round line end.
+const ROUND_END_SEGMENTS: usize = 8;
+
+/// Creates the outline of a line with a varying width, as a single closed polygon.
+///
+/// `widths` holds the full width of the line at each point. Consecutive points are joined with (limited) miter joins.
+/// If `round_ends` is set, each end of the line is capped with a semicircle.
+pub(crate) fn variable_width_outline(
+    points: &[Vec2],
+    widths: &[f32],
+    round_ends: bool,
+) -> Vec<Vec2> {
+    // Drop points that are on top of each other, since they have no direction.
+    let mut line: Vec<(Vec2, f32)> = Vec::with_capacity(points.len());
+    for (&p, &width) in points.iter().zip(widths) {
+        match line.last_mut() {
+            Some(last) if last.0.distance_squared(p) <= f32::EPSILON => last.1 = last.1.max(width),
+            _ => line.push((p, width)),
+        }
+    }
+
+    if line.len() < 2 {
+        return Vec::new();
+    }
+
+    let segment_normals: Vec<Vec2> = line
+        .windows(2)
+        .map(|w| (w[1].0 - w[0].0).normalize().perp())
+        .collect();
+
+    let mut left = Vec::with_capacity(line.len());
+    let mut right = Vec::with_capacity(line.len());
+
+    for (i, &(p, width)) in line.iter().enumerate() {
+        let half_width = width / 2.0;
+
+        let offset = if i == 0 {
+            segment_normals[0] * half_width
+        } else if i == line.len() - 1 {
+            segment_normals[i - 1] * half_width
+        } else {
+            let previous = segment_normals[i - 1];
+            let miter = previous + segment_normals[i];
+
+            // If the line turns back on itself, there is no sensible miter.
+            if miter.length_squared() <= f32::EPSILON {
+                previous * half_width
+            } else {
+                let miter = miter.normalize();
+                let length = (half_width / miter.dot(previous)).min(half_width * MITER_LIMIT);
+
+                miter * length
+            }
+        };
+
+        left.push(p + offset);
+        right.push(p - offset);
+    }
+
+    let mut outline = Vec::with_capacity(left.len() + right.len() + ROUND_END_SEGMENTS * 2);
+
+    outline.extend(left);
+    if round_ends {
+        let (p, width) = line[line.len() - 1];
+        outline.extend(half_circle(
+            p,
+            segment_normals[segment_normals.len() - 1],
+            width / 2.0,
+        ));
+    }
+
+    outline.extend(right.into_iter().rev());
+    if round_ends {
+        let (p, width) = line[0];
+        outline.extend(half_circle(p, -segment_normals[0], width / 2.0));
+    }
+
+    outline
+}
+
+/// Creates the inner points of a semicircle, going clockwise from `center + normal * radius` to `center - normal * radius`.
+fn half_circle(center: Vec2, normal: Vec2, radius: f32) -> impl Iterator<Item = Vec2> {
+    let start = Mat2::from_cols(normal, -normal.perp());
+
+    (1..ROUND_END_SEGMENTS).map(move |i| {
+        let angle = i as f32 / ROUND_END_SEGMENTS as f32 * std::f32::consts::PI;
+
+        center + start.mul_vec2(Vec2::new(angle.cos(), angle.sin())) * radius
+    })
+}
+
+/// Finds the horizontal spans of pixels covered by a polygon (with the even-odd rule), clipped to an image of the given size.
+/// The points are in pixel space, and pixels are covered if their centers are inside the polygon.
+/// Returns the row and range of columns of each span.
+pub(crate) fn polygon_spans(
+    points: &[Vec2],
+    width: usize,
+    height: usize,
+) -> Vec<(usize, Range<usize>)> {
+    let mut spans = Vec::new();
+
+    if points.len() < 3 {
+        return spans;
+    }
+
+    let min_y = points.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+    let max_y = points.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+
+    let start_row = (min_y - 0.5).ceil().max(0.0) as usize;
+    let end_row = ((max_y - 0.5).floor() + 1.0).clamp(0.0, height as f32) as usize;
+
+    let mut crossings = Vec::new();
+
+    for y in start_row..end_row {
+        let center = y as f32 + 0.5;
+
+        crossings.clear();
+        for (i, a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+
+            if (a.y <= center) != (b.y <= center) {
+                crossings.push(a.x + (center - a.y) / (b.y - a.y) * (b.x - a.x));
+            }
+        }
+
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        for span in crossings.chunks_exact(2) {
+            let start = ((span[0] - 0.5).ceil().max(0.0) as usize).min(width);
+            let end = ((span[1] - 0.5).ceil().max(0.0) as usize).min(width);
+
+            if start < end {
+                spans.push((y, start..end));
+            }
+        }
+    }
+
+    spans
+}
+
+/// Covers a polygon (with the even-odd rule) in parallel lines, at the given spacing and angle (in radians).
+/// Lines are placed on a grid shared by every polygon, so hatching lines up across neighbouring shapes.
+/// Every other line runs backwards, so that they can be drawn back and forth.
+pub(crate) fn hatch_lines(points: &[Vec2], spacing: f32, angle: f32) -> Vec<(Vec2, Vec2)> {
+    hatch_rows(points, spacing, angle)
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// The same lines as [hatch_lines], grouped into rows. A row holds more than one line where it crosses a gap in the polygon.
+pub(crate) fn hatch_rows(points: &[Vec2], spacing: f32, angle: f32) -> Vec<Vec<(Vec2, Vec2)>> {
+    let mut rows = Vec::new();
+
+    if points.len() < 3 || spacing <= 0.0 {
+        return rows;
+    }
+
+    // The polygon is turned so that the lines are horizontal, then the lines are turned back.
+    let rotation = Mat2::from_angle(-angle);
+    let inverse = Mat2::from_angle(angle);
+    let rotated: Vec<Vec2> = points.iter().map(|p| rotation.mul_vec2(*p)).collect();
+
+    let min_y = rotated.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+    let max_y = rotated
+        .iter()
+        .map(|p| p.y)
+        .fold(f32::NEG_INFINITY, f32::max);
+
+    let mut row = (min_y / spacing - 0.5).ceil() as i64;
+    let mut crossings = Vec::new();
+
+    loop {
+        let y = (row as f32 + 0.5) * spacing;
+
+        if y > max_y {
+            break;
+        }
+
+        crossings.clear();
+        for (i, a) in rotated.iter().enumerate() {
+            let b = rotated[(i + 1) % rotated.len()];
+
+            if (a.y <= y) != (b.y <= y) {
+                crossings.push(a.x + (y - a.y) / (b.y - a.y) * (b.x - a.x));
+            }
+        }
+
+        crossings.sort_by(|a, b| a.total_cmp(b));
+
+        // Spans with no length are where the line only touches a corner of the polygon.
+        let mut spans: Vec<(Vec2, Vec2)> = crossings
+            .chunks_exact(2)
+            .filter(|span| span[0] < span[1])
+            .map(|span| {
+                (
+                    inverse.mul_vec2(Vec2::new(span[0], y)),
+                    inverse.mul_vec2(Vec2::new(span[1], y)),
+                )
+            })
+            .collect();
+
+        if row % 2 != 0 {
+            spans.reverse();
+            spans.iter_mut().for_each(|(a, b)| std::mem::swap(a, b));
+        }
+
+        if !spans.is_empty() {
+            rows.push(spans);
+        }
+
+        row += 1;
+    }
+
+    rows
+}
+
+/// Splits `total` into `parts` shares that differ by at most one, and gets the share of one of them.
+/// The shares of all parts add up to `total`.
+pub(crate) fn even_share(total: usize, parts: usize, part: usize) -> usize {
+    total / parts + usize::from(part < total % parts)
+}
+
+/// Creates a cumulative distribution from a list of (non-negative) weights, for use with [sample_cumulative].
+pub(crate) fn cumulative<I: IntoIterator<Item = f32>>(weights: I) -> Vec<f32> {
+    let mut total = 0.0;
+
+    weights
+        .into_iter()
+        .map(|weight| {
+            total += weight.max(0.0);
+            total
+        })
+        .collect()
+}
+
+/// Picks a random index from a cumulative distribution created by [cumulative].
+/// Each index is picked with a probability proportional to its weight.
+pub(crate) fn sample_cumulative<R: Rng + ?Sized>(cumulative: &[f32], rng: &mut R) -> usize {
+    let total = cumulative.last().copied().unwrap_or(0.0);
+
+    if total <= 0.0 {
+        return rng.gen_range(0..cumulative.len().max(1));
+    }
+
+    let target = rng.gen_range(0.0..total);
+
+    cumulative
+        .partition_point(|&c| c <= target)
+        .min(cumulative.len() - 1)
+}
+
+/// Weighs each pixel of an image (row by row) by the strength of the edges there, for picking points with [cumulative].
+///
+/// At a bias of `0.0` every pixel has the same weight, and at `1.0` the weights are proportional to edge strength.
+pub(crate) fn edge_importance(image: &RasterCanvas, blur_radius: usize, bias: f32) -> Vec<f32> {
+    let width = image.width();
+    let bias = bias.clamp(0.0, 1.0);
+
+    // Without any bias every pixel is equally important, so there's no need to find the gradients.
+    if bias == 0.0 {
+        return vec![1.0; width * image.height()];
+    }
+
+    let gradients: Vec<f32> = (0..width * image.height())
+        .map(|i| {
+            image
+                .get_gradient(i % width, i / width, blur_radius)
+                .length()
+        })
+        .collect();
+    let max_gradient = gradients.iter().copied().fold(f32::EPSILON, f32::max);
+
+    gradients
+        .into_iter()
+        .map(|gradient| (1.0 - bias) + bias * gradient / max_gradient)
+        .collect()
+}
+
+/// Moves every edge of a convex polygon inwards by a distance, returning `None` if nothing would be left.
+pub(crate) fn inset_convex_polygon(polygon: &[Vec2], distance: f32) -> Option<Vec<Vec2>> {
+    let mut points: Vec<Vec2> = Vec::with_capacity(polygon.len());
+    for &p in polygon {
+        match points.last() {
+            Some(last) if last.distance_squared(p) <= f32::EPSILON => (),
+            _ => points.push(p),
+        }
+    }
+    while points.len() > 1 && points[0].distance_squared(points[points.len() - 1]) <= f32::EPSILON {
+        points.pop();
+    }
+
+    let n = points.len();
+    if n < 3 {
+        return None;
+    }
+
+    let area: f32 = (0..n)
+        .map(|i| points[i].perp_dot(points[(i + 1) % n]))
+        .sum();
+    if area.abs() <= f32::EPSILON {
+        return None;
+    }
+
+    // The left side of each edge is inside for counter-clockwise polygons, and the right side for clockwise ones.
+    let edges: Vec<(Vec2, Vec2)> = (0..n)
+        .map(|i| {
+            let direction = points[(i + 1) % n] - points[i];
+
+            (
+                points[i] + direction.perp().normalize() * distance * area.signum(),
+                direction,
+            )
+        })
+        .collect();
+
+    let inset: Vec<Vec2> = (0..n)
+        .map(|i| {
+            let (p1, d1) = edges[(i + n - 1) % n];
+            let (p2, d2) = edges[i];
+
+            let denominator = d1.perp_dot(d2);
+            if denominator.abs() <= f32::EPSILON {
+                p2
+            } else {
+                p1 + d1 * ((p2 - p1).perp_dot(d2) / denominator)
+            }
+        })
+        .collect();
+
+    // Insetting too far turns edges around.
+    let collapsed = (0..n).any(|i| (inset[(i + 1) % n] - inset[i]).dot(edges[i].1) <= 0.0);
+
+    if collapsed {
+        None
+    } else {
+        Some(inset)
+    }
+}
+
+/// Smooth value noise, ranging from 0 to 1, with features about one unit apart.
+/// Different seeds give unrelated noise.
+pub(crate) fn value_noise(p: Vec2, seed: u32) -> f32 {
+    let lattice = |x: i32, y: i32| {
+        let mut hash = (x as u32).wrapping_mul(0x8da6_b343)
+            ^ (y as u32).wrapping_mul(0xd816_3841)
+            ^ seed.wrapping_mul(0xcb1a_b31f);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(0x5bd1_e995);
+        hash ^= hash >> 15;
+
+        hash as f32 / u32::MAX as f32
+    };
+
+    let cell = p.floor();
+    let (x, y) = (cell.x as i32, cell.y as i32);
+
+    // Smoothstep keeps the noise from having creases along the lattice.
+    let t = p - cell;
+    let t = t * t * (Vec2::splat(3.0) - 2.0 * t);
+
+    let bottom = lattice(x, y) + (lattice(x + 1, y) - lattice(x, y)) * t.x;
+    let top = lattice(x, y + 1) + (lattice(x + 1, y + 1) - lattice(x, y + 1)) * t.x;
+
+    bottom + (top - bottom) * t.y
+}
+
+/// Buckets points into a grid of square cells, for quickly finding the points near another.
+pub(crate) struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialHash {
+    pub fn new(points: &[Vec2], cell_size: f32) -> Self {
+        let mut hash = Self {
+            cell_size: cell_size.max(f32::EPSILON),
+            cells: HashMap::new(),
+        };
+
+        for (i, &p) in points.iter().enumerate() {
+            hash.cells.entry(hash.cell(p)).or_default().push(i);
+        }
+
+        hash
+    }
+
+    fn cell(&self, p: Vec2) -> (i32, i32) {
+        let cell = (p / self.cell_size).floor();
+
+        (cell.x as i32, cell.y as i32)
+    }
+
+    /// The indices of every point in the cell of `p` and the cells around it.
+    /// This includes every point within `cell_size` of `p`, along with some further away.
+    pub fn nearby(&self, p: Vec2) -> impl Iterator<Item = usize> + '_ {
+        let (x, y) = self.cell(p);
+
+        (-1..=1)
+            .flat_map(move |dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+}
+
+/// Traces the lines where a grid of values crosses a level, using marching squares.
+/// The values are stored row by row, and each one is sampled at the center of its pixel.
+/// Returns polylines in pixel space. Closed lines end with their first point.
+pub(crate) fn contour_lines(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    level: f32,
+) -> Vec<Vec<Vec2>> {
+    // Contour points lie on the edges between samples, identified by the first sample and whether the edge is vertical.
+    type Edge = (usize, usize, bool);
+
+    let value = |x: usize, y: usize| values[y * width + x];
+    let inside = |x: usize, y: usize| value(x, y) >= level;
+
+    let mut segments: Vec<(Edge, Edge)> = Vec::new();
+
+    for y in 0..height.saturating_sub(1) {
+        for x in 0..width.saturating_sub(1) {
+            let case = inside(x, y) as u8
+                | (inside(x + 1, y) as u8) << 1
+                | (inside(x + 1, y + 1) as u8) << 2
+                | (inside(x, y + 1) as u8) << 3;
+
+            let top = (x, y, false);
+            let right = (x + 1, y, true);
+            let bottom = (x, y + 1, false);
+            let left = (x, y, true);
+
+            // Saddles are resolved by the average of the corners.
+            let center_inside =
+                (value(x, y) + value(x + 1, y) + value(x + 1, y + 1) + value(x, y + 1)) / 4.0
+                    >= level;
+
+            match case {
+                1 | 14 => segments.push((left, top)),
+                2 | 13 => segments.push((top, right)),
+                3 | 12 => segments.push((left, right)),
+                4 | 11 => segments.push((right, bottom)),
+                6 | 9 => segments.push((top, bottom)),
+                7 | 8 => segments.push((left, bottom)),
+                5 => {
+                    if center_inside {
+                        segments.push((top, right));
+                        segments.push((bottom, left));
+                    } else {
+                        segments.push((left, top));
+                        segments.push((right, bottom));
+                    }
+                }
+                10 => {
+                    if center_inside {
+                        segments.push((left, top));
+                        segments.push((right, bottom));
+                    } else {
+                        segments.push((top, right));
+                        segments.push((bottom, left));
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    // Each edge is shared by at most two segments, so chains are followed through the edges.
+    let mut by_edge: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (i, &(a, b)) in segments.iter().enumerate() {
+        by_edge.entry(a).or_default().push(i);
+        by_edge.entry(b).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+
+    let follow = |edge: Edge, used: &mut Vec<bool>| -> Option<Edge> {
+        let &i = by_edge.get(&edge)?.iter().find(|&&i| !used[i])?;
+        used[i] = true;
+
+        let (a, b) = segments[i];
+        Some(if a == edge { b } else { a })
+    };
+
+    let point = |(x, y, vertical): Edge| {
+        let (x2, y2) = if vertical { (x, y + 1) } else { (x + 1, y) };
+        let (a, b) = (value(x, y), value(x2, y2));
+        let t = if a == b {
+            0.5
+        } else {
+            ((level - a) / (b - a)).clamp(0.0, 1.0)
+        };
+
+        Vec2::new(x as f32, y as f32).lerp(Vec2::new(x2 as f32, y2 as f32), t) + 0.5
+    };
+
+    let mut lines = Vec::new();
+
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+
+        let (a, b) = segments[start];
+        let mut chain = VecDeque::from(vec![a, b]);
+
+        while let Some(edge) = chain.back().and_then(|&edge| follow(edge, &mut used)) {
+            chain.push_back(edge);
+        }
+        while let Some(edge) = chain.front().and_then(|&edge| follow(edge, &mut used)) {
+            chain.push_front(edge);
+        }
+
+        lines.push(chain.into_iter().map(point).collect());
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use barium::Vec2;
+
+    use super::hatch_lines;
+
+    #[test]
+    fn hatches_through_vertices() {
+        // Lines are at y = -1.5, -0.5 and 0.5, which each pass through corners of the diamond.
+        // The corners at the top and bottom are only touched, so only the line through the widest part is kept.
+        let diamond = [
+            Vec2::new(0.0, -1.5),
+            Vec2::new(1.0, -0.5),
+            Vec2::new(0.0, 0.5),
+            Vec2::new(-1.0, -0.5),
+        ];
+
+        assert_eq!(
+            hatch_lines(&diamond, 1.0, 0.0),
+            [(Vec2::new(1.0, -0.5), Vec2::new(-1.0, -0.5))]
+        );
+
+        // A line through a corner where the outline carries on past it crosses the outline once there.
+        let triangle = [Vec2::ZERO, Vec2::new(2.0, 0.5), Vec2::Y];
+
+        assert_eq!(
+            hatch_lines(&triangle, 1.0, 0.0),
+            [(Vec2::new(0.0, 0.5), Vec2::new(2.0, 0.5))]
+        );
+    }
+}
@@ -1,6 +1,17 @@
 mod canvas;
 mod helpers;
+mod palette;
+mod plotter;
 pub mod sketchers;
+mod triangulation;
 
-pub use canvas::{OmniCanvas, RasterCanvas, VectorCanvas, VectorizerStyle};
+pub use canvas::{
+    DensityCanvas, LayeredCanvas, OmniCanvas, RasterCanvas, ToneMapping, VectorCanvas,
+    VectorizerStyle,
+};
+pub use palette::{ColorMap, ColorSpace, CosinePalette, Gradient, Palette, PaletteExtraction};
+pub use plotter::{
+    pen_up_distance, GCodeSettings, PathOptimizationReport, PathOptimizer, PlotterFormat,
+    PlotterSettings,
+};
 pub use barium;
@@ -0,0 +1,643 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+
+use barium::{Color, Stroke};
+
+use crate::canvas::VectorCanvas;
+
+/// The space colors are blended in by a [Gradient].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorSpace {
+    /// Blends the color components directly, as they are stored.
+    Srgb,
+    /// Blends physical light intensities, which keeps mixes of saturated colors from going muddy.
+    LinearRgb,
+    /// Blends in Oklab, a perceptual space, so that lightness changes evenly.
+    Oklab,
+    /// Blends hue, saturation and lightness, taking the short way around the hue circle.
+    Hsl,
+}
+
+impl ColorSpace {
+    fn encode(self, color: Color) -> [f32; 3] {
+        let rgb = [color.r(), color.g(), color.b()];
+
+        match self {
+            Self::Srgb => rgb,
+            Self::LinearRgb => rgb.map(srgb_to_linear),
+            Self::Oklab => linear_to_oklab(rgb.map(srgb_to_linear)),
+            Self::Hsl => rgb_to_hsl(rgb),
+        }
+    }
+
+    fn decode(self, [x, y, z]: [f32; 3], alpha: f32) -> Color {
+        let [r, g, b] = match self {
+            Self::Srgb => [x, y, z],
+            Self::LinearRgb => [x, y, z].map(linear_to_srgb),
+            Self::Oklab => oklab_to_linear([x, y, z]).map(linear_to_srgb),
+            Self::Hsl => hsl_to_rgb([x, y, z]),
+        };
+
+        Color::new(
+            r.clamp(0.0, 1.0),
+            g.clamp(0.0, 1.0),
+            b.clamp(0.0, 1.0),
+            alpha,
+        )
+    }
+
+    fn mix(self, a: Color, b: Color, t: f32) -> Color {
+        let [mut ax, ay, az] = self.encode(a);
+        let [mut bx, by, bz] = self.encode(b);
+
+        // Hue wraps around, so blend the short way.
+        if self == Self::Hsl {
+            if bx - ax > 0.5 {
+                ax += 1.0;
+            } else if ax - bx > 0.5 {
+                bx += 1.0;
+            }
+        }
+
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+
+        self.decode([mix(ax, bx), mix(ay, by), mix(az, bz)], mix(a.a(), b.a()))
+    }
+}
+
+/// Colors at positions from `0.0` to `1.0`, blended in between.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Gradient {
+    stops: Vec<(f32, Color)>,
+    space: ColorSpace,
+}
+
+impl Gradient {
+    /// Creates a gradient from colors at positions. The stops are sorted by position.
+    pub fn new(mut stops: Vec<(f32, Color)>, space: ColorSpace) -> Self {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Self { stops, space }
+    }
+
+    /// Creates a gradient from evenly spaced colors.
+    pub fn evenly_spaced(colors: &[Color], space: ColorSpace) -> Self {
+        let last = colors.len().saturating_sub(1).max(1) as f32;
+
+        Self::new(
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, color)| (i as f32 / last, *color))
+                .collect(),
+            space,
+        )
+    }
+
+    /// Blends from black to white.
+    pub fn grayscale() -> Self {
+        Self::evenly_spaced(&[Color::black(), Color::white()], ColorSpace::Srgb)
+    }
+
+    /// Matplotlib's viridis colormap: perceptually uniform, from dark blue through green to yellow.
+    pub fn viridis() -> Self {
+        Self::from_hex_stops(&[
+            0x440154, 0x482475, 0x414487, 0x355f8d, 0x2a788e, 0x21918c, 0x22a884, 0x44bf70,
+            0x7ad151, 0xbddf26, 0xfde725,
+        ])
+    }
+
+    /// Matplotlib's magma colormap: perceptually uniform, from black through purple to pale yellow.
+    pub fn magma() -> Self {
+        Self::from_hex_stops(&[
+            0x000004, 0x140e36, 0x3b0f70, 0x641a80, 0x8c2981, 0xb73779, 0xde4968, 0xf7705c,
+            0xfe9f6d, 0xfecf92, 0xfcfdbf,
+        ])
+    }
+
+    /// Matplotlib's inferno colormap: perceptually uniform, from black through red to bright yellow.
+    pub fn inferno() -> Self {
+        Self::from_hex_stops(&[
+            0x000004, 0x160b39, 0x420a68, 0x6a176e, 0x932667, 0xbc3754, 0xdd513a, 0xf37819,
+            0xfca50a, 0xf6d746, 0xfcffa4,
+        ])
+    }
+
+    /// Matplotlib's plasma colormap: perceptually uniform, from blue through magenta to yellow.
+    pub fn plasma() -> Self {
+        Self::from_hex_stops(&[
+            0x0d0887, 0x41049d, 0x6a00a8, 0x8f0da4, 0xb12a90, 0xcc4778, 0xe16462, 0xf2844b,
+            0xfca636, 0xfcce25, 0xf0f921,
+        ])
+    }
+
+    /// Evenly spaced opaque colors, written as `0xRRGGBB`. The stops are close enough together to blend directly.
+    fn from_hex_stops(hex: &[u32]) -> Self {
+        let colors: Vec<Color> = hex
+            .iter()
+            .map(|hex| {
+                let channel = |shift: u32| ((hex >> shift) & 0xff) as f32 / 255.0;
+
+                Color::new(channel(16), channel(8), channel(0), 1.0)
+            })
+            .collect();
+
+        Self::evenly_spaced(&colors, ColorSpace::Srgb)
+    }
+
+    /// Gets the color at a position. Positions outside of the stops get the color of the nearest stop.
+    pub fn sample(&self, t: f32) -> Color {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Color::transparent(),
+        };
+
+        if t.is_nan() || t <= first.0 {
+            return first.1;
+        }
+        if t >= last.0 {
+            return last.1;
+        }
+
+        let i = self.stops.partition_point(|(position, _)| *position <= t);
+        let (a, b) = (self.stops[i - 1], self.stops[i]);
+        let span = b.0 - a.0;
+
+        if span <= 0.0 {
+            return b.1;
+        }
+
+        self.space.mix(a.1, b.1, (t - a.0) / span)
+    }
+
+    pub fn stops(&self) -> &[(f32, Color)] {
+        &self.stops
+    }
+
+    pub const fn space(&self) -> ColorSpace {
+        self.space
+    }
+}
+
+/// A palette defined by `a + b cos(2π (c t + d))` for each of red, green and blue, as described by Inigo Quilez.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CosinePalette {
+    /// The middle of each channel.
+    pub a: [f32; 3],
+    /// How far each channel swings around the middle.
+    pub b: [f32; 3],
+    /// How many times each channel cycles from `0.0` to `1.0`.
+    pub c: [f32; 3],
+    /// The phase of each channel, in cycles.
+    pub d: [f32; 3],
+}
+
+impl CosinePalette {
+    /// A full rainbow.
+    pub fn rainbow() -> Self {
+        Self {
+            a: [0.5; 3],
+            b: [0.5; 3],
+            c: [1.0; 3],
+            d: [0.0, 0.33, 0.67],
+        }
+    }
+
+    /// Gets the color at a position. The palette repeats outside of `0.0` to `1.0` (unless the frequencies are fractional).
+    pub fn sample(&self, t: f32) -> Color {
+        let channel = |i: usize| {
+            (self.a[i] + self.b[i] * (TAU * (self.c[i] * t + self.d[i])).cos()).clamp(0.0, 1.0)
+        };
+
+        Color::new(channel(0), channel(1), channel(2), 1.0)
+    }
+}
+
+/// Maps a value from `0.0` to `1.0` to a color.
+///
+/// Sketchers take these in one of two ways, and document what value they map:
+/// * A setting that is only a color, like the foreground of a [CelestialSketcher](crate::sketchers::CelestialSketcher),
+///   is a color map instead. A single color converts into a mapping that ignores the value.
+/// * Where the color is part of something larger, like a [Stroke] or the shapes of a [Tile](crate::sketchers::Tile),
+///   that is kept as it is, and an optional color map beside it (named after it, like `stroke_colors`) replaces only its color.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ColorMap {
+    Solid(Color),
+    Gradient(Gradient),
+    Cosine(CosinePalette),
+}
+
+impl ColorMap {
+    pub fn sample(&self, t: f32) -> Color {
+        match self {
+            Self::Solid(color) => *color,
+            Self::Gradient(gradient) => gradient.sample(t),
+            Self::Cosine(palette) => palette.sample(t),
+        }
+    }
+}
+
+impl From<Color> for ColorMap {
+    fn from(color: Color) -> Self {
+        Self::Solid(color)
+    }
+}
+
+impl From<Gradient> for ColorMap {
+    fn from(gradient: Gradient) -> Self {
+        Self::Gradient(gradient)
+    }
+}
+
+impl From<CosinePalette> for ColorMap {
+    fn from(palette: CosinePalette) -> Self {
+        Self::Cosine(palette)
+    }
+}
+
+/// How [RasterCanvas::extract_palette](crate::RasterCanvas::extract_palette) picks the colors of a palette.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PaletteExtraction {
+    /// Repeatedly splits the group of colors with the most variation in two, along its widest axis.
+    /// Like median cut, but each group is cut where its two halves vary the least rather than at its median,
+    /// so that a small group of distinct colors keeps a color of its own.
+    VarianceCut,
+    /// Refines the colors found by variance cut with a number of iterations of k-means clustering, which fits the image more closely.
+    KMeans { iterations: usize },
+}
+
+/// A set of colors, each with a weight, such as how much of an image it covers.
+///
+/// Colors are compared in Oklab, so that the closest color is the one that looks closest.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Palette {
+    colors: Vec<(Color, f32)>,
+    oklab: Vec<[f32; 3]>,
+}
+
+impl Palette {
+    /// Creates a palette of equally weighted colors.
+    pub fn new(colors: &[Color]) -> Self {
+        Self::weighted(colors.iter().map(|color| (*color, 1.0)).collect())
+    }
+
+    /// Creates a palette of colors with weights.
+    pub fn weighted(colors: Vec<(Color, f32)>) -> Self {
+        Self {
+            oklab: colors
+                .iter()
+                .map(|(color, _)| ColorSpace::Oklab.encode(*color))
+                .collect(),
+            colors,
+        }
+    }
+
+    /// Extracts up to `count` colors, weighted by how often they appear (and by their alpha).
+    /// The weights add up to `1.0`, and the colors are sorted from the most to the least common.
+    pub(crate) fn extract(
+        colors: impl Iterator<Item = Color>,
+        count: usize,
+        method: PaletteExtraction,
+    ) -> Self {
+        // Similar colors are binned together first, so large images stay fast.
+        let mut bins: HashMap<[u8; 3], ([f32; 3], f32)> = HashMap::new();
+
+        for color in colors {
+            let weight = color.a();
+
+            if weight <= 0.0 {
+                continue;
+            }
+
+            let key = [color.r(), color.g(), color.b()].map(|c| (c.clamp(0.0, 1.0) * 31.0) as u8);
+            let lab = ColorSpace::Oklab.encode(color);
+
+            let (sum, total) = bins.entry(key).or_insert(([0.0; 3], 0.0));
+            for (sum, value) in sum.iter_mut().zip(lab) {
+                *sum += value * weight;
+            }
+            *total += weight;
+        }
+
+        let points: Vec<([f32; 3], f32)> = bins
+            .into_values()
+            .map(|(sum, weight)| (sum.map(|s| s / weight), weight))
+            .collect();
+
+        if points.is_empty() || count == 0 {
+            return Self::weighted(Vec::new());
+        }
+
+        let mut centers = variance_cut(points.clone(), count);
+
+        if let PaletteExtraction::KMeans { iterations } = method {
+            for _ in 0..iterations {
+                let mut sums = vec![([0.0; 3], 0.0); centers.len()];
+
+                for (p, weight) in &points {
+                    let (sum, total) = &mut sums[nearest(&centers, *p)];
+
+                    for (sum, value) in sum.iter_mut().zip(p) {
+                        *sum += value * weight;
+                    }
+                    *total += weight;
+                }
+
+                let mut moved = false;
+
+                for (center, (sum, total)) in centers.iter_mut().zip(sums) {
+                    if total > 0.0 {
+                        let next = sum.map(|s| s / total);
+
+                        moved |= distance_squared(*center, next) > 1e-10;
+                        *center = next;
+                    }
+                }
+
+                if !moved {
+                    break;
+                }
+            }
+        }
+
+        let mut weights = vec![0.0; centers.len()];
+        for (p, weight) in &points {
+            weights[nearest(&centers, *p)] += weight;
+        }
+
+        let total: f32 = weights.iter().sum();
+
+        let mut colors: Vec<(Color, f32)> = centers
+            .into_iter()
+            .zip(weights)
+            .filter(|(_, weight)| *weight > 0.0)
+            .map(|(center, weight)| (ColorSpace::Oklab.decode(center, 1.0), weight / total))
+            .collect();
+
+        colors.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        Self::weighted(colors)
+    }
+
+    pub fn colors(&self) -> &[(Color, f32)] {
+        &self.colors
+    }
+
+    /// Finds the closest color of the palette, keeping the alpha of the original color.
+    /// An empty palette leaves colors as they are.
+    pub fn nearest(&self, color: Color) -> Color {
+        if self.colors.is_empty() {
+            return color;
+        }
+
+        let closest = self.colors[nearest(&self.oklab, ColorSpace::Oklab.encode(color))].0;
+
+        Color::new(closest.r(), closest.g(), closest.b(), color.a())
+    }
+
+    /// Replaces every fill and stroke color of a [VectorCanvas] with the closest color of the palette.
+    pub fn remap_vector(&self, canvas: &mut VectorCanvas) {
+        for shape in canvas.as_raw_mut() {
+            if let Some(fill) = &mut shape.fill {
+                *fill = self.nearest(*fill);
+            }
+            if let Some(stroke) = &mut shape.stroke {
+                stroke.color = self.nearest(stroke.color);
+            }
+        }
+    }
+
+    /// Creates a gradient through the colors of the palette, from the darkest to the lightest.
+    pub fn to_gradient(&self, space: ColorSpace) -> Gradient {
+        let mut colors: Vec<(Color, f32)> = self
+            .colors
+            .iter()
+            .zip(&self.oklab)
+            .map(|((color, _), lab)| (*color, lab[0]))
+            .collect();
+
+        colors.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        Gradient::evenly_spaced(
+            &colors
+                .into_iter()
+                .map(|(color, _)| color)
+                .collect::<Vec<_>>(),
+            space,
+        )
+    }
+}
+
+impl From<Palette> for ColorMap {
+    fn from(palette: Palette) -> Self {
+        Self::Gradient(palette.to_gradient(ColorSpace::Oklab))
+    }
+}
+
+fn distance_squared(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// The index of the closest of a set of colors (in the same space).
+fn nearest(colors: &[[f32; 3]], p: [f32; 3]) -> usize {
+    colors
+        .iter()
+        .enumerate()
+        .min_by(|a, b| distance_squared(*a.1, p).total_cmp(&distance_squared(*b.1, p)))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// The weighted mean of a group of colors.
+fn weighted_mean(points: &[([f32; 3], f32)]) -> [f32; 3] {
+    let total: f32 = points.iter().map(|(_, weight)| weight).sum();
+
+    [0, 1, 2].map(|axis| {
+        points
+            .iter()
+            .map(|(p, weight)| p[axis] * weight)
+            .sum::<f32>()
+            / total.max(f32::EPSILON)
+    })
+}
+
+/// Splits weighted colors into up to `count` groups by variance cut, returning the mean of each.
+fn variance_cut(points: Vec<([f32; 3], f32)>, count: usize) -> Vec<[f32; 3]> {
+    let error = |points: &[([f32; 3], f32)]| {
+        let mean = weighted_mean(points);
+
+        points
+            .iter()
+            .map(|(p, weight)| distance_squared(*p, mean) * weight)
+            .sum::<f32>()
+    };
+
+    let mut groups = vec![points];
+
+    while groups.len() < count {
+        let split = groups
+            .iter()
+            .enumerate()
+            .filter(|(_, group)| group.len() > 1)
+            .map(|(i, group)| (i, error(group)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut group = match split {
+            Some((i, _)) => groups.swap_remove(i),
+            None => break,
+        };
+
+        let range = |axis: usize| {
+            let (min, max) = group
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), (p, _)| {
+                    (min.min(p[axis]), max.max(p[axis]))
+                });
+
+            max - min
+        };
+        let axis = (0..3)
+            .max_by(|a, b| range(*a).total_cmp(&range(*b)))
+            .unwrap_or(0);
+
+        group.sort_by(|a, b| a.0[axis].total_cmp(&b.0[axis]));
+
+        // Cut where the two halves vary the least, so that a small group of distinct colors is not split across
+        // (and averaged into) its neighbours.
+        let mut totals = ([0.0; 3], 0.0, 0.0);
+        let sums: Vec<([f32; 3], f32, f32)> = group
+            .iter()
+            .map(|(p, weight)| {
+                for (sum, value) in totals.0.iter_mut().zip(p) {
+                    *sum += value * weight;
+                }
+                totals.1 += weight;
+                totals.2 += p.iter().map(|value| value * value).sum::<f32>() * weight;
+
+                totals
+            })
+            .collect();
+
+        let variation = |(sum, total, squares): ([f32; 3], f32, f32)| {
+            squares - sum.iter().map(|s| s * s).sum::<f32>() / total.max(f32::EPSILON)
+        };
+
+        let cut = (1..group.len())
+            .min_by(|a, b| {
+                let split = |i: usize| {
+                    let (sum, total, squares) = sums[i - 1];
+                    let rest = (
+                        [0, 1, 2].map(|axis| totals.0[axis] - sum[axis]),
+                        totals.1 - total,
+                        totals.2 - squares,
+                    );
+
+                    variation((sum, total, squares)) + variation(rest)
+                };
+
+                split(*a).total_cmp(&split(*b))
+            })
+            .unwrap_or(1);
+
+        let upper = group.split_off(cut);
+
+        groups.push(group);
+        groups.push(upper);
+    }
+
+    groups.iter().map(|group| weighted_mean(group)).collect()
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.max(0.0).powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn linear_to_oklab([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let l = (0.41222146 * r + 0.53633255 * g + 0.051445995 * b).cbrt();
+    let m = (0.2119035 * r + 0.6806995 * g + 0.10739696 * b).cbrt();
+    let s = (0.08830246 * r + 0.28171885 * g + 0.6299787 * b).cbrt();
+
+    [
+        0.21045426 * l + 0.7936178 * m - 0.004072047 * s,
+        1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+        0.025904037 * l + 0.78277177 * m - 0.80867577 * s,
+    ]
+}
+
+fn oklab_to_linear([l, a, b]: [f32; 3]) -> [f32; 3] {
+    let l_ = (l + 0.39633778 * a + 0.21580376 * b).powi(3);
+    let m_ = (l - 0.105561346 * a - 0.06385417 * b).powi(3);
+    let s_ = (l - 0.08948418 * a - 1.2914855 * b).powi(3);
+
+    [
+        4.0767417 * l_ - 3.3077116 * m_ + 0.23096994 * s_,
+        -1.268438 * l_ + 2.6097574 * m_ - 0.34131938 * s_,
+        -0.0041960864 * l_ - 0.7034186 * m_ + 1.7076147 * s_,
+    ]
+}
+
+/// Converts to hue (in turns), saturation and lightness.
+fn rgb_to_hsl([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+
+    if delta <= f32::EPSILON {
+        return [0.0, 0.0, lightness];
+    }
+
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs()).max(f32::EPSILON);
+
+    let hue = if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+
+    [hue / 6.0, saturation, lightness]
+}
+
+fn hsl_to_rgb([hue, saturation, lightness]: [f32; 3]) -> [f32; 3] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+
+    let [r, g, b] = match h as usize {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+
+    let m = lightness - chroma / 2.0;
+
+    [r + m, g + m, b + m]
+}
+
+/// Recolors a stroke by an optional color map. Without one, the stroke keeps its own color.
+pub(crate) fn map_stroke(stroke: Stroke, colors: Option<&ColorMap>, t: f32) -> Stroke {
+    match colors {
+        Some(colors) => Stroke {
+            color: colors.sample(t),
+            ..stroke
+        },
+        None => stroke,
+    }
+}
@@ -0,0 +1,921 @@
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_4;
+use std::fmt::Write;
+
+use barium::{Color, Shape, Vec2};
+
+use crate::canvas::{LayeredCanvas, VectorCanvas};
+use crate::helpers::{hatch_lines, SpatialHash};
+
+/// Reorders the shapes of a [VectorCanvas] to cut down on the distance a pen plotter travels with the pen lifted.
+///
+/// Shapes are grouped into layers by their colors (and stroke width), since each needs its own pen.
+/// Layers are drawn in the order their colors first appear, and only the order of shapes within each layer changes.
+/// Shapes with a fill are treated as closed, so they can be started from any of their points.
+/// Shapes without any points are dropped.
+#[derive(Clone, Copy, Debug)]
+pub struct PathOptimizer {
+    /// Paths without a fill whose ends are within this distance of each other are joined into one.
+    /// At `0.0`, only paths whose ends are exactly the same are joined. Negative values disable joining.
+    pub merge_tolerance: f32,
+    /// Whether paths without a fill may be drawn backwards.
+    pub allow_reversal: bool,
+    /// The most times the order is swept with 2-opt, which undoes detours left by the greedy ordering.
+    /// Layers with paths that can't be reversed are not swept.
+    pub two_opt_passes: usize,
+}
+
+/// How much a [PathOptimizer] helped.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PathOptimizationReport {
+    pub paths_before: usize,
+    pub paths_after: usize,
+    /// The distance travelled with the pen lifted before optimizing. See [pen_up_distance].
+    pub pen_up_before: f32,
+    /// The distance travelled with the pen lifted after optimizing.
+    pub pen_up_after: f32,
+}
+
+impl Default for PathOptimizer {
+    fn default() -> Self {
+        Self {
+            merge_tolerance: 0.0,
+            allow_reversal: true,
+            two_opt_passes: 4,
+        }
+    }
+}
+
+/// A path as it will be drawn, which may be backwards.
+#[derive(Clone, Copy)]
+struct Visit {
+    path: usize,
+    reversed: bool,
+}
+
+impl PathOptimizer {
+    /// 2-opt only tries reversing runs of up to this many paths, which keeps it fast on canvases with many of them.
+    const TWO_OPT_WINDOW: usize = 64;
+
+    /// Optimizes the order of the shapes of a canvas in place.
+    pub fn optimize(&self, canvas: &mut VectorCanvas) -> PathOptimizationReport {
+        let shapes = std::mem::take(canvas.as_raw_mut());
+
+        let paths_before = shapes.len();
+        let pen_up_before = pen_up_distance_between(&shapes);
+
+        let mut layers: Vec<Vec<Shape>> = Vec::new();
+        let mut layer_indices = HashMap::new();
+
+        for shape in shapes.into_iter().filter(|shape| !shape.points.is_empty()) {
+            let index = *layer_indices.entry(layer_key(&shape)).or_insert_with(|| {
+                layers.push(Vec::new());
+                layers.len() - 1
+            });
+
+            layers[index].push(shape);
+        }
+
+        let mut position = None;
+
+        for layer in layers {
+            let mut layer = self.merge(layer);
+            let mut order = self.order(&mut layer, position);
+
+            self.two_opt(&layer, &mut order);
+
+            let mut layer: Vec<Option<Shape>> = layer.into_iter().map(Some).collect();
+
+            for visit in order {
+                let mut shape = layer[visit.path].take().unwrap();
+
+                if visit.reversed {
+                    shape.points.reverse();
+                }
+
+                position = Some(end(&shape));
+                canvas.as_raw_mut().push(shape);
+            }
+        }
+
+        PathOptimizationReport {
+            paths_before,
+            paths_after: canvas.as_raw().len(),
+            pen_up_before,
+            pen_up_after: pen_up_distance(canvas),
+        }
+    }
+
+    /// Joins paths without a fill that share ends.
+    fn merge(&self, shapes: Vec<Shape>) -> Vec<Shape> {
+        if self.merge_tolerance < 0.0 {
+            return shapes;
+        }
+
+        let (open, mut merged): (Vec<Shape>, Vec<Shape>) = shapes
+            .into_iter()
+            .partition(|shape| shape.fill.is_none() && shape.points.len() >= 2);
+
+        // Every path has two ends: `2 * i` is the start of path `i`, and `2 * i + 1` is its end.
+        let ends: Vec<Vec2> = open
+            .iter()
+            .flat_map(|shape| [shape.points[0], shape.points[shape.points.len() - 1]])
+            .collect();
+
+        // Tiny cells would overflow the grid coordinates, and are no faster.
+        let hash = SpatialHash::new(&ends, self.merge_tolerance.max(1e-4));
+        let mut used = vec![false; open.len()];
+
+        // Finds an unused path with an end at a point, along with whether it has to be reversed to join there.
+        // Paths are added after the end of the merged path, or before its start.
+        let find = |used: &[bool], point: Vec2, before_start: bool| {
+            hash.nearby(point)
+                .filter(|end| !used[end / 2])
+                .filter(|end| ends[*end].distance(point) <= self.merge_tolerance)
+                .map(|end| (end / 2, (end % 2 == 0) == before_start))
+                .find(|(_, reversed)| self.allow_reversal || !reversed)
+        };
+
+        for i in 0..open.len() {
+            if used[i] {
+                continue;
+            }
+
+            used[i] = true;
+
+            let mut points = open[i].points.clone();
+
+            while let Some((next, reversed)) = find(&used, points[points.len() - 1], false) {
+                used[next] = true;
+
+                if reversed {
+                    points.extend(open[next].points.iter().rev().skip(1));
+                } else {
+                    points.extend(open[next].points.iter().skip(1));
+                }
+            }
+
+            while let Some((previous, reversed)) = find(&used, points[0], true) {
+                used[previous] = true;
+
+                let mut joined = open[previous].points.clone();
+                if reversed {
+                    joined.reverse();
+                }
+
+                joined.pop();
+                joined.extend(points);
+                points = joined;
+            }
+
+            merged.push(Shape {
+                points,
+                stroke: open[i].stroke,
+                fill: None,
+            });
+        }
+
+        merged
+    }
+
+    /// Orders paths greedily, always drawing the path that starts closest to where the last one ended next.
+    /// Closed paths are rotated to start from their closest point.
+    fn order(&self, shapes: &mut [Shape], start: Option<Vec2>) -> Vec<Visit> {
+        // Every point a path can be started from, along with the path and the index of the point.
+        let mut entries: Vec<(Vec2, usize, usize)> = Vec::new();
+
+        for (i, shape) in shapes.iter().enumerate() {
+            if shape.fill.is_some() {
+                for (j, point) in shape.points.iter().enumerate() {
+                    entries.push((*point, i, j));
+                }
+            } else {
+                entries.push((shape.points[0], i, 0));
+
+                if self.allow_reversal {
+                    entries.push((
+                        shape.points[shape.points.len() - 1],
+                        i,
+                        shape.points.len() - 1,
+                    ));
+                }
+            }
+        }
+
+        let mut visited = vec![false; shapes.len()];
+        let mut order = Vec::with_capacity(shapes.len());
+
+        let mut grid = NearestGrid::new(&entries);
+        let mut position = start.or_else(|| entries.first().map(|entry| entry.0));
+
+        while let Some(entry) = position.and_then(|p| grid.nearest(p, &entries, &visited)) {
+            let (_, path, point) = entries[entry];
+            let shape = &mut shapes[path];
+
+            visited[path] = true;
+
+            let reversed = shape.fill.is_none() && point != 0;
+            if shape.fill.is_some() {
+                shape.points.rotate_left(point);
+            }
+
+            order.push(Visit { path, reversed });
+
+            position = Some(if reversed {
+                shape.points[0]
+            } else {
+                end(shape)
+            });
+        }
+
+        order
+    }
+
+    /// Sweeps the order with 2-opt, reversing runs of paths wherever that shortens the travel around them.
+    fn two_opt(&self, shapes: &[Shape], order: &mut [Visit]) {
+        let reversible = self.allow_reversal || shapes.iter().all(|shape| shape.fill.is_some());
+
+        if !reversible || order.len() < 3 {
+            return;
+        }
+
+        let start = |visit: Visit| {
+            let shape = &shapes[visit.path];
+
+            if visit.reversed {
+                end(shape)
+            } else {
+                shape.points[0]
+            }
+        };
+        let finish = |visit: Visit| {
+            let shape = &shapes[visit.path];
+
+            if visit.reversed {
+                shape.points[0]
+            } else {
+                end(shape)
+            }
+        };
+
+        for _ in 0..self.two_opt_passes {
+            let mut improved = false;
+
+            for i in 1..order.len() - 1 {
+                for j in i + 1..(i + Self::TWO_OPT_WINDOW).min(order.len()) {
+                    let (a, b, c) = (finish(order[i - 1]), start(order[i]), finish(order[j]));
+                    let d = order.get(j + 1).map(|visit| start(*visit));
+
+                    let before = a.distance(b) + d.map_or(0.0, |d| c.distance(d));
+                    let after = a.distance(c) + d.map_or(0.0, |d| b.distance(d));
+
+                    if after < before - f32::EPSILON {
+                        order[i..=j].reverse();
+
+                        for visit in &mut order[i..=j] {
+                            if shapes[visit.path].fill.is_none() {
+                                visit.reversed = !visit.reversed;
+                            }
+                        }
+
+                        improved = true;
+                    }
+                }
+            }
+
+            if !improved {
+                break;
+            }
+        }
+    }
+}
+
+/// Where [PlotterSettings::export] places a canvas on the paper, and how it draws fills.
+///
+/// Plotters can't fill shapes, so fills are drawn as hatch lines instead.
+/// Each color gets its own pen, in the order the colors first appear. The fill and the stroke of a shape may use different pens.
+#[derive(Clone, Debug)]
+pub struct PlotterSettings {
+    /// The size of the paper, in millimeters.
+    pub paper_size: Vec2,
+    /// The space left empty along each edge of the paper, in millimeters.
+    pub margin: f32,
+    /// The corners of the part of canvas space that is scaled to fit the paper (within the margins).
+    /// If `None`, everything on the canvas is fit.
+    pub view: Option<(Vec2, Vec2)>,
+    /// The distance between hatch lines, in millimeters.
+    pub hatch_spacing: f32,
+    /// The angle of hatch lines, in radians.
+    pub hatch_angle: f32,
+    /// Whether to trace the outline of each fill (with the fill's pen) around its hatching.
+    pub outline_fills: bool,
+}
+
+impl Default for PlotterSettings {
+    /// A4 paper in portrait, with 45 degree hatching.
+    fn default() -> Self {
+        Self {
+            paper_size: Vec2::new(210.0, 297.0),
+            margin: 10.0,
+            view: None,
+            hatch_spacing: 1.0,
+            hatch_angle: FRAC_PI_4,
+            outline_fills: true,
+        }
+    }
+}
+
+/// The commands a G-code plotter uses. Every position is written in millimeters.
+#[derive(Clone, Debug)]
+pub struct GCodeSettings {
+    /// Lifts the pen off the paper.
+    pub pen_up: String,
+    /// Puts the pen on the paper.
+    pub pen_down: String,
+    /// How fast the pen moves while drawing, in millimeters per minute.
+    pub draw_feed_rate: f32,
+    /// How fast the pen moves while lifted, in millimeters per minute. If `None`, it moves as fast as it can (with `G0`).
+    pub travel_feed_rate: Option<f32>,
+    /// Run between colors when they are exported into the same file. `{tool}` is replaced by the number of the next pen, starting at 1.
+    pub tool_change: String,
+}
+
+impl Default for GCodeSettings {
+    fn default() -> Self {
+        Self {
+            pen_up: "G0 Z5".to_string(),
+            pen_down: "G1 Z0 F500".to_string(),
+            draw_feed_rate: 1500.0,
+            travel_feed_rate: None,
+            tool_change: "M0 (Change to pen {tool})".to_string(),
+        }
+    }
+}
+
+/// The language plotter output is written in.
+#[derive(Clone, Debug)]
+pub enum PlotterFormat {
+    GCode(GCodeSettings),
+    /// HP-GL, where each color is drawn with the pen of the same number, starting at 1.
+    Hpgl,
+}
+
+impl PlotterFormat {
+    /// The usual file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::GCode(_) => "gcode",
+            Self::Hpgl => "hpgl",
+        }
+    }
+}
+
+/// The lines drawn by one pen, in millimeters on the paper.
+struct PenLayer {
+    color: Color,
+    paths: Vec<Vec<Vec2>>,
+}
+
+impl PlotterSettings {
+    /// HP-GL plotters move in steps of 0.025 millimeters.
+    const HPGL_UNITS_PER_MILLIMETER: f32 = 40.0;
+
+    /// Exports a canvas into a single file, changing pens between colors.
+    pub fn export(&self, canvas: &VectorCanvas, format: &PlotterFormat) -> String {
+        write_plot(&self.layers(canvas), format)
+    }
+
+    /// Exports a canvas into a file for each color, in the order the colors first appear.
+    pub fn export_per_color(
+        &self,
+        canvas: &VectorCanvas,
+        format: &PlotterFormat,
+    ) -> Vec<(Color, String)> {
+        self.layers(canvas)
+            .into_iter()
+            .map(|layer| {
+                (
+                    layer.color,
+                    write_plot(std::slice::from_ref(&layer), format),
+                )
+            })
+            .collect()
+    }
+
+    /// Exports each layer of a canvas into its own file, along with the name of the layer.
+    /// Every layer is placed on the paper the same way, so that they line up.
+    pub fn export_layers(
+        &self,
+        canvas: &LayeredCanvas,
+        format: &PlotterFormat,
+    ) -> Vec<(String, String)> {
+        let settings = Self {
+            view: Some(self.view.unwrap_or_else(|| bounds(&canvas.flatten()))),
+            ..self.clone()
+        };
+
+        canvas
+            .layers()
+            .iter()
+            .map(|(name, layer)| (name.clone(), settings.export(layer, format)))
+            .collect()
+    }
+
+    /// Places every shape on the paper, and sorts the lines by pen.
+    fn layers(&self, canvas: &VectorCanvas) -> Vec<PenLayer> {
+        let shapes = canvas.as_raw();
+
+        let (min, max) = self.view.unwrap_or_else(|| bounds(canvas));
+
+        if !(min.is_finite() && max.is_finite()) {
+            return Vec::new();
+        }
+
+        let area = (self.paper_size - Vec2::splat(self.margin * 2.0)).max(Vec2::ZERO);
+        let scale = (area / (max - min).max(Vec2::splat(f32::EPSILON))).min_element();
+        let center = (min + max) / 2.0;
+
+        let to_paper = |p: Vec2| (p - center) * scale + self.paper_size / 2.0;
+
+        let mut layers: Vec<PenLayer> = Vec::new();
+        let mut layer_indices = HashMap::new();
+
+        let mut add = |color: Color, path: Vec<Vec2>| {
+            let key = [color.r(), color.g(), color.b(), color.a()].map(f32::to_bits);
+            let index = *layer_indices.entry(key).or_insert_with(|| {
+                layers.push(PenLayer {
+                    color,
+                    paths: Vec::new(),
+                });
+                layers.len() - 1
+            });
+
+            layers[index].paths.push(path);
+        };
+
+        for shape in shapes.iter().filter(|shape| !shape.points.is_empty()) {
+            let mut outline: Vec<Vec2> = shape.points.iter().map(|p| to_paper(*p)).collect();
+
+            if let Some(fill) = shape.fill {
+                // Hatching is worked out on the paper, so the spacing stays the same however the canvas is scaled.
+                for (a, b) in hatch_lines(&outline, self.hatch_spacing, self.hatch_angle) {
+                    add(fill, vec![a, b]);
+                }
+
+                outline.push(outline[0]);
+
+                if self.outline_fills {
+                    add(fill, outline.clone());
+                }
+            }
+
+            if let Some(stroke) = shape.stroke {
+                add(stroke.color, outline);
+            }
+        }
+
+        layers
+    }
+}
+
+/// The corners of the box around every point of a canvas. They are infinite if the canvas is empty.
+fn bounds(canvas: &VectorCanvas) -> (Vec2, Vec2) {
+    canvas.as_raw().iter().flat_map(|shape| &shape.points).fold(
+        (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+        |(min, max), p| (min.min(*p), max.max(*p)),
+    )
+}
+
+/// Writes the commands to draw layers of lines, changing pens between them.
+fn write_plot(layers: &[PenLayer], format: &PlotterFormat) -> String {
+    let mut output = String::new();
+
+    // Writing to a string can't fail.
+    match format {
+        PlotterFormat::GCode(settings) => {
+            let position = |p: Vec2| format!("X{:.3} Y{:.3}", p.x, p.y);
+
+            writeln!(output, "G21").unwrap();
+            writeln!(output, "G90").unwrap();
+            writeln!(output, "{}", settings.pen_up).unwrap();
+
+            for (i, layer) in layers.iter().enumerate() {
+                if i > 0 {
+                    writeln!(
+                        output,
+                        "{}",
+                        settings.tool_change.replace("{tool}", &(i + 1).to_string())
+                    )
+                    .unwrap();
+                }
+
+                for path in &layer.paths {
+                    match settings.travel_feed_rate {
+                        Some(feed_rate) => {
+                            writeln!(output, "G1 {} F{}", position(path[0]), feed_rate)
+                        }
+                        None => writeln!(output, "G0 {}", position(path[0])),
+                    }
+                    .unwrap();
+
+                    writeln!(output, "{}", settings.pen_down).unwrap();
+
+                    for (j, p) in path.iter().enumerate().skip(1) {
+                        if j == 1 {
+                            writeln!(output, "G1 {} F{}", position(*p), settings.draw_feed_rate)
+                        } else {
+                            writeln!(output, "G1 {}", position(*p))
+                        }
+                        .unwrap();
+                    }
+
+                    writeln!(output, "{}", settings.pen_up).unwrap();
+                }
+            }
+
+            writeln!(output, "G0 X0 Y0").unwrap();
+        }
+        PlotterFormat::Hpgl => {
+            let position = |p: Vec2| {
+                let p = (p * PlotterSettings::HPGL_UNITS_PER_MILLIMETER).round();
+
+                format!("{},{}", p.x as i64, p.y as i64)
+            };
+
+            writeln!(output, "IN;").unwrap();
+
+            for (i, layer) in layers.iter().enumerate() {
+                writeln!(output, "SP{};", i + 1).unwrap();
+
+                for path in &layer.paths {
+                    writeln!(output, "PU{};", position(path[0])).unwrap();
+
+                    let points: Vec<String> = path.iter().map(|p| position(*p)).collect();
+                    writeln!(output, "PD{};", points.join(",")).unwrap();
+                }
+            }
+
+            writeln!(output, "PU;SP0;").unwrap();
+        }
+    }
+
+    output
+}
+
+/// The distance a pen plotter travels with the pen lifted while drawing a canvas, from the start of the first shape.
+/// Shapes with a fill are closed, so they end where they start.
+pub fn pen_up_distance(canvas: &VectorCanvas) -> f32 {
+    pen_up_distance_between(canvas.as_raw())
+}
+
+fn pen_up_distance_between(shapes: &[Shape]) -> f32 {
+    let mut position: Option<Vec2> = None;
+    let mut distance = 0.0;
+
+    for shape in shapes.iter().filter(|shape| !shape.points.is_empty()) {
+        if let Some(position) = position {
+            distance += position.distance(shape.points[0]);
+        }
+
+        position = Some(end(shape));
+    }
+
+    distance
+}
+
+/// Where the pen is left after drawing a (non-empty) shape.
+fn end(shape: &Shape) -> Vec2 {
+    if shape.fill.is_some() {
+        shape.points[0]
+    } else {
+        shape.points[shape.points.len() - 1]
+    }
+}
+
+/// Identifies the pens a shape needs.
+fn layer_key(shape: &Shape) -> (Option<[u32; 5]>, Option<[u32; 4]>) {
+    let bits = |color: Color| [color.r(), color.g(), color.b(), color.a()].map(f32::to_bits);
+
+    (
+        shape.stroke.map(|stroke| {
+            let [r, g, b, a] = bits(stroke.color);
+
+            [r, g, b, a, stroke.width.to_bits()]
+        }),
+        shape.fill.map(bits),
+    )
+}
+
+/// A grid of points, for finding the closest point that belongs to a path that hasn't been visited yet.
+struct NearestGrid {
+    cell_size: f32,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    /// The first and last occupied cells along each axis.
+    min: (i64, i64),
+    max: (i64, i64),
+}
+
+impl NearestGrid {
+    fn new(entries: &[(Vec2, usize, usize)]) -> Self {
+        let (min, max) = entries.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), entry| (min.min(entry.0), max.max(entry.0)),
+        );
+
+        // About one point per cell. When every point is in the same place, any size will do.
+        // Tiny cells would overflow the grid coordinates, and are no faster.
+        let extent = (max - min).max_element();
+        let cell_size = if extent > 0.0 && extent.is_finite() {
+            (extent / (entries.len() as f32).sqrt()).max(1e-4)
+        } else {
+            1.0
+        };
+
+        let mut grid = Self {
+            cell_size,
+            cells: HashMap::new(),
+            min: (0, 0),
+            max: (0, 0),
+        };
+
+        if let Some(first) = entries.first() {
+            grid.min = grid.cell(first.0);
+            grid.max = grid.min;
+        }
+
+        for (i, entry) in entries.iter().enumerate() {
+            let cell = grid.cell(entry.0);
+
+            grid.min = (grid.min.0.min(cell.0), grid.min.1.min(cell.1));
+            grid.max = (grid.max.0.max(cell.0), grid.max.1.max(cell.1));
+            grid.cells.entry(cell).or_default().push(i);
+        }
+
+        grid
+    }
+
+    fn cell(&self, p: Vec2) -> (i64, i64) {
+        let cell = (p / self.cell_size).floor();
+
+        (cell.x as i64, cell.y as i64)
+    }
+
+    /// Searches rings of cells outwards from a point, until no closer point can be found.
+    /// Only the parts of rings that overlap the occupied cells are searched, so points far from the grid are cheap too.
+    /// Points of visited paths are dropped from the grid as they are found.
+    fn nearest(
+        &mut self,
+        p: Vec2,
+        entries: &[(Vec2, usize, usize)],
+        visited: &[bool],
+    ) -> Option<usize> {
+        let (x, y) = self.cell(p);
+        let (min, max) = (self.min, self.max);
+
+        // The rings that reach the nearest and the furthest occupied cells.
+        let first_ring = (min.0 - x)
+            .max(x - max.0)
+            .max(min.1 - y)
+            .max(y - max.1)
+            .max(0);
+        let last_ring = (x - min.0)
+            .abs()
+            .max((max.0 - x).abs())
+            .max((y - min.1).abs())
+            .max((max.1 - y).abs());
+
+        let mut best: Option<(f32, usize)> = None;
+
+        for ring in first_ring..=last_ring {
+            // Every cell of this ring is at least this far away.
+            if let Some((distance, _)) = best {
+                if distance <= (ring - 1) as f32 * self.cell_size {
+                    break;
+                }
+            }
+
+            let columns = (x - ring).max(min.0)..=(x + ring).min(max.0);
+            let rows = (y - ring + 1).max(min.1)..=(y + ring - 1).min(max.1);
+
+            // The rows above and below, and the columns to either side without the corners the rows already have.
+            let mut cells: Vec<(i64, i64)> = Vec::new();
+
+            let edges = if ring == 0 {
+                vec![0]
+            } else {
+                vec![-ring, ring]
+            };
+
+            for &edge in &edges {
+                if (min.1..=max.1).contains(&(y + edge)) {
+                    cells.extend(columns.clone().map(|column| (column, y + edge)));
+                }
+                if ring > 0 && (min.0..=max.0).contains(&(x + edge)) {
+                    cells.extend(rows.clone().map(|row| (x + edge, row)));
+                }
+            }
+
+            for cell in cells {
+                if let Some(indices) = self.cells.get_mut(&cell) {
+                    indices.retain(|i| !visited[entries[*i].1]);
+
+                    for &i in indices.iter() {
+                        let distance = entries[i].0.distance(p);
+
+                        if !matches!(best, Some((best, _)) if best <= distance) {
+                            best = Some((distance, i));
+                        }
+                    }
+                }
+            }
+        }
+
+        best.map(|(_, i)| i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use barium::{Color, LineEnd, Stroke, Vec2};
+
+    use super::{GCodeSettings, PathOptimizer, PlotterFormat, PlotterSettings};
+    use crate::canvas::VectorCanvas;
+
+    fn stroke(color: Color) -> Option<Stroke> {
+        Some(Stroke {
+            color,
+            width: 0.01,
+            line_end: LineEnd::Round,
+        })
+    }
+
+    #[test]
+    fn optimizes_paths_that_share_a_start() {
+        // Every entry point is at the origin, so the grid has no extent, while every path ends far from it.
+        let mut canvas = VectorCanvas::default();
+
+        for i in 0..8 {
+            let angle = i as f32 * std::f32::consts::FRAC_PI_4;
+            canvas.draw_shape(
+                vec![Vec2::ZERO, Vec2::new(angle.cos(), angle.sin()) * 100.0],
+                stroke(Color::black()),
+                None,
+            );
+        }
+
+        let report = PathOptimizer {
+            allow_reversal: false,
+            merge_tolerance: -1.0,
+            ..Default::default()
+        }
+        .optimize(&mut canvas);
+
+        assert_eq!(report.paths_after, 8);
+        assert!(canvas
+            .as_raw()
+            .iter()
+            .all(|shape| shape.points[0] == Vec2::ZERO));
+    }
+
+    /// A red line across a blue square, on a 100mm square sheet with no margin,
+    /// so that canvas space is scaled by 100 and hatch lines are 20mm apart.
+    fn two_colors() -> (VectorCanvas, PlotterSettings) {
+        let mut canvas = VectorCanvas::default();
+        canvas.draw_shape(
+            vec![Vec2::ZERO, Vec2::ONE],
+            stroke(Color::new(1.0, 0.0, 0.0, 1.0)),
+            None,
+        );
+        canvas.draw_shape(
+            vec![Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y],
+            None,
+            Some(Color::new(0.0, 0.0, 1.0, 1.0)),
+        );
+
+        let settings = PlotterSettings {
+            paper_size: Vec2::splat(100.0),
+            margin: 0.0,
+            hatch_spacing: 20.0,
+            hatch_angle: 0.0,
+            ..Default::default()
+        };
+
+        (canvas, settings)
+    }
+
+    const GCODE_HEADER: &str = "G21\nG90\nG0 Z5\n";
+
+    const GCODE_RED: &str = "\
+G0 X0.000 Y0.000
+G1 Z0 F500
+G1 X100.000 Y100.000 F1500
+G0 Z5
+";
+
+    const GCODE_BLUE: &str = "\
+G0 X0.000 Y10.000
+G1 Z0 F500
+G1 X100.000 Y10.000 F1500
+G0 Z5
+G0 X100.000 Y30.000
+G1 Z0 F500
+G1 X0.000 Y30.000 F1500
+G0 Z5
+G0 X0.000 Y50.000
+G1 Z0 F500
+G1 X100.000 Y50.000 F1500
+G0 Z5
+G0 X100.000 Y70.000
+G1 Z0 F500
+G1 X0.000 Y70.000 F1500
+G0 Z5
+G0 X0.000 Y90.000
+G1 Z0 F500
+G1 X100.000 Y90.000 F1500
+G0 Z5
+G0 X0.000 Y0.000
+G1 Z0 F500
+G1 X100.000 Y0.000 F1500
+G1 X100.000 Y100.000
+G1 X0.000 Y100.000
+G1 X0.000 Y0.000
+G0 Z5
+";
+
+    const GCODE_FOOTER: &str = "G0 X0 Y0\n";
+
+    const HPGL_RED: &str = "\
+SP1;
+PU0,0;
+PD0,0,4000,4000;
+";
+
+    const HPGL_BLUE: &str = "\
+PU0,400;
+PD0,400,4000,400;
+PU4000,1200;
+PD4000,1200,0,1200;
+PU0,2000;
+PD0,2000,4000,2000;
+PU4000,2800;
+PD4000,2800,0,2800;
+PU0,3600;
+PD0,3600,4000,3600;
+PU0,0;
+PD0,0,4000,0,4000,4000,0,4000,0,0;
+";
+
+    #[test]
+    fn exports_gcode() {
+        let (canvas, settings) = two_colors();
+        let format = PlotterFormat::GCode(GCodeSettings::default());
+
+        assert_eq!(
+            settings.export(&canvas, &format),
+            [
+                GCODE_HEADER,
+                GCODE_RED,
+                "M0 (Change to pen 2)\n",
+                GCODE_BLUE,
+                GCODE_FOOTER,
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn exports_hpgl() {
+        let (canvas, settings) = two_colors();
+
+        assert_eq!(
+            settings.export(&canvas, &PlotterFormat::Hpgl),
+            ["IN;\n", HPGL_RED, "SP2;\n", HPGL_BLUE, "PU;SP0;\n"].concat()
+        );
+    }
+
+    #[test]
+    fn exports_each_color_separately() {
+        let (canvas, settings) = two_colors();
+
+        let gcode = settings.export_per_color(&canvas, &PlotterFormat::GCode(Default::default()));
+        let hpgl = settings.export_per_color(&canvas, &PlotterFormat::Hpgl);
+
+        let colors: Vec<Color> = gcode.iter().map(|(color, _)| *color).collect();
+        assert_eq!(
+            colors,
+            [
+                Color::new(1.0, 0.0, 0.0, 1.0),
+                Color::new(0.0, 0.0, 1.0, 1.0)
+            ]
+        );
+
+        // Each file starts over with the first pen.
+        assert_eq!(gcode[0].1, [GCODE_HEADER, GCODE_RED, GCODE_FOOTER].concat());
+        assert_eq!(
+            gcode[1].1,
+            [GCODE_HEADER, GCODE_BLUE, GCODE_FOOTER].concat()
+        );
+        assert_eq!(hpgl[0].1, ["IN;\n", HPGL_RED, "PU;SP0;\n"].concat());
+        assert_eq!(hpgl[1].1, ["IN;\nSP1;\n", HPGL_BLUE, "PU;SP0;\n"].concat());
+    }
+}
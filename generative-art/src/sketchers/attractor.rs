@@ -0,0 +1,331 @@
+use barium::Vec2;
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::Sketcher;
+use crate::canvas::{DensityCanvas, OmniCanvas, ToneMapping};
+use crate::helpers::even_share;
+use crate::palette::ColorMap;
+
+/// A map (or system of equations) iterated by an [AttractorSketcher].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AttractorMap {
+    /// `x = sin(a y) + c cos(a x)`, `y = sin(b x) + d cos(b y)`
+    Clifford { a: f32, b: f32, c: f32, d: f32 },
+    /// `x = sin(a y) - cos(b x)`, `y = sin(c x) - cos(d y)`
+    DeJong { a: f32, b: f32, c: f32, d: f32 },
+    /// `x = d sin(a x) - sin(b y)`, `y = c cos(a x) + cos(b y)`
+    Svensson { a: f32, b: f32, c: f32, d: f32 },
+    /// The Lorenz system, integrated with a time step of `dt` and projected to 2D.
+    Lorenz {
+        sigma: f32,
+        rho: f32,
+        beta: f32,
+        dt: f32,
+    },
+    /// The Aizawa system, integrated with a time step of `dt` and projected to 2D.
+    Aizawa {
+        a: f32,
+        b: f32,
+        c: f32,
+        d: f32,
+        e: f32,
+        f: f32,
+        dt: f32,
+    },
+}
+
+impl AttractorMap {
+    /// Clifford parameters with a well-known shape.
+    pub fn clifford() -> Self {
+        Self::Clifford {
+            a: -1.4,
+            b: 1.6,
+            c: 1.0,
+            d: 0.7,
+        }
+    }
+
+    /// Peter de Jong parameters with a well-known shape.
+    pub fn de_jong() -> Self {
+        Self::DeJong {
+            a: 1.4,
+            b: -2.3,
+            c: 2.4,
+            d: -2.1,
+        }
+    }
+
+    /// Johnny Svensson parameters with a well-known shape.
+    pub fn svensson() -> Self {
+        Self::Svensson {
+            a: 1.5,
+            b: -1.8,
+            c: 1.6,
+            d: 0.9,
+        }
+    }
+
+    /// The classic parameters of the Lorenz system.
+    pub fn lorenz() -> Self {
+        Self::Lorenz {
+            sigma: 10.0,
+            rho: 28.0,
+            beta: 8.0 / 3.0,
+            dt: 0.005,
+        }
+    }
+
+    /// The usual parameters of the Aizawa system.
+    pub fn aizawa() -> Self {
+        Self::Aizawa {
+            a: 0.95,
+            b: 0.7,
+            c: 0.6,
+            d: 3.5,
+            e: 0.25,
+            f: 0.1,
+            dt: 0.01,
+        }
+    }
+
+    /// Computes the next point, in three dimensions. Two-dimensional maps leave `z` at zero.
+    fn next(&self, [x, y, z]: [f32; 3]) -> [f32; 3] {
+        match *self {
+            Self::Clifford { a, b, c, d } => [
+                (a * y).sin() + c * (a * x).cos(),
+                (b * x).sin() + d * (b * y).cos(),
+                0.0,
+            ],
+            Self::DeJong { a, b, c, d } => [
+                (a * y).sin() - (b * x).cos(),
+                (c * x).sin() - (d * y).cos(),
+                0.0,
+            ],
+            Self::Svensson { a, b, c, d } => [
+                d * (a * x).sin() - (b * y).sin(),
+                c * (a * x).cos() + (b * y).cos(),
+                0.0,
+            ],
+            Self::Lorenz {
+                sigma,
+                rho,
+                beta,
+                dt,
+            } => [
+                x + sigma * (y - x) * dt,
+                y + (x * (rho - z) - y) * dt,
+                z + (x * y - beta * z) * dt,
+            ],
+            Self::Aizawa {
+                a,
+                b,
+                c,
+                d,
+                e,
+                f,
+                dt,
+            } => [
+                x + ((z - b) * x - d * y) * dt,
+                y + (d * x + (z - b) * y) * dt,
+                z + (c + a * z - z * z * z / 3.0 - (x * x + y * y) * (1.0 + e * z)
+                    + f * z * x * x * x)
+                    * dt,
+            ],
+        }
+    }
+
+    /// A starting point near the attractor.
+    fn start<R: Rng + ?Sized>(&self, rng: &mut R) -> [f32; 3] {
+        let jitter = |rng: &mut R| rng.gen_range(-0.1..0.1);
+
+        match self {
+            Self::Lorenz { .. } => [1.0 + jitter(rng), 1.0 + jitter(rng), 1.0 + jitter(rng)],
+            _ => [jitter(rng), jitter(rng), jitter(rng)],
+        }
+    }
+}
+
+/// Which plane the points of an [AttractorSketcher] are projected onto. Only matters for three-dimensional systems.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AttractorProjection {
+    XY,
+    XZ,
+    YZ,
+}
+
+impl AttractorProjection {
+    fn project(&self, [x, y, z]: [f32; 3]) -> Vec2 {
+        match self {
+            Self::XY => Vec2::new(x, y),
+            Self::XZ => Vec2::new(x, z),
+            Self::YZ => Vec2::new(y, z),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AttractorSketcherSettings {
+    /// The map to iterate.
+    pub map: AttractorMap,
+    /// The plane to project three-dimensional systems onto.
+    pub projection: AttractorProjection,
+    /// The total number of points to plot.
+    pub points: usize,
+    /// The width of the output image.
+    pub width: usize,
+    /// The height of the output image.
+    pub height: usize,
+    /// Space left around the attractor, as a fraction of the canvas.
+    pub margin: f32,
+    /// How densities are mapped to colors.
+    pub tone_mapping: ToneMapping,
+    /// Maps densities to colors, from empty pixels (`0.0`) to the densest ones (`1.0`).
+    pub colormap: ColorMap,
+}
+
+/// Plots the density of the orbit of a strange attractor.
+pub struct AttractorSketcher {
+    settings: AttractorSketcherSettings,
+    canvas: DensityCanvas,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+impl AttractorSketcher {
+    /// The number of separate orbits, which can be followed in parallel.
+    const ORBITS: usize = 16;
+    /// The number of times progress is reported.
+    const BATCHES: usize = 100;
+    /// The number of points skipped at the start of each orbit, before it settles onto the attractor.
+    const WARM_UP: usize = 1000;
+    /// The number of points used to find the bounds of the attractor.
+    const BOUNDS_SAMPLES: usize = 100_000;
+
+    pub fn new(
+        settings: AttractorSketcherSettings,
+        #[cfg(feature = "small-rng")] seed: u64,
+    ) -> Self {
+        Self {
+            canvas: DensityCanvas::new(settings.width, settings.height),
+            settings,
+            #[cfg(feature = "small-rng")]
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Gets the accumulated densities, for custom tone mapping.
+    pub fn density(&self) -> &DensityCanvas {
+        &self.canvas
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let mut rng = &mut self.rng;
+
+        let map = self.settings.map;
+        let projection = self.settings.projection;
+
+        let mut orbits: Vec<[f32; 3]> = (0..Self::ORBITS)
+            .map(|_| (0..Self::WARM_UP).fold(map.start(&mut rng), |state, _| map.next(state)))
+            .collect();
+
+        // Follow one orbit for a while to find the bounds of the attractor, so it can be fit into the canvas.
+        let (min, max) = (0..Self::BOUNDS_SAMPLES)
+            .scan(orbits[0], |state, _| {
+                *state = map.next(*state);
+                Some(projection.project(*state))
+            })
+            .filter(|p| p.is_finite())
+            .fold(
+                (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+                |(min, max), p| (min.min(p), max.max(p)),
+            );
+
+        let minor = self.settings.width.min(self.settings.height).max(1) as f32;
+        let half_extent =
+            Vec2::new(self.settings.width as f32, self.settings.height as f32) / minor;
+
+        let center = (min + max) / 2.0;
+        let scale = ((half_extent * 2.0 * (1.0 - self.settings.margin))
+            / (max - min).max(Vec2::splat(f32::EPSILON)))
+        .min_element();
+
+        let points = self.settings.points;
+
+        // Each orbit plots its share of the points in each batch.
+        let plot =
+            |state: &mut [f32; 3], orbit: usize, batch: usize, canvas: &mut DensityCanvas| {
+                let share = even_share(
+                    points,
+                    Self::ORBITS * Self::BATCHES,
+                    batch * Self::ORBITS + orbit,
+                );
+
+                for _ in 0..share {
+                    *state = map.next(*state);
+
+                    canvas.add((projection.project(*state) - center) * scale, 1.0);
+                }
+            };
+
+        // Orbits are split between threads, each with its own canvas to plot into.
+        #[cfg(feature = "parallel")]
+        let orbits_per_thread = Self::ORBITS.div_ceil(rayon::current_num_threads());
+        #[cfg(feature = "parallel")]
+        let mut canvases: Vec<DensityCanvas> = orbits
+            .chunks(orbits_per_thread)
+            .map(|_| DensityCanvas::new(self.settings.width, self.settings.height))
+            .collect();
+
+        for batch in 0..Self::BATCHES {
+            before_iter(batch as f32 / Self::BATCHES as f32);
+
+            #[cfg(feature = "parallel")]
+            orbits
+                .par_chunks_mut(orbits_per_thread)
+                .zip(canvases.par_iter_mut())
+                .enumerate()
+                .for_each(|(chunk, (states, canvas))| {
+                    for (i, state) in states.iter_mut().enumerate() {
+                        plot(state, chunk * orbits_per_thread + i, batch, canvas);
+                    }
+                });
+            #[cfg(not(feature = "parallel"))]
+            for (orbit, state) in orbits.iter_mut().enumerate() {
+                plot(state, orbit, batch, &mut self.canvas);
+            }
+        }
+
+        #[cfg(feature = "parallel")]
+        for canvas in &canvases {
+            self.canvas.merge(canvas);
+        }
+    }
+}
+
+impl<P> Sketcher<P> for AttractorSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas
+            .to_raster(self.settings.tone_mapping, &self.settings.colormap)
+            .into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas
+            .to_raster(self.settings.tone_mapping, &self.settings.colormap)
+            .into()
+    }
+}
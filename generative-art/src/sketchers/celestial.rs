@@ -4,9 +4,10 @@ use rand::prelude::Distribution;
 #[cfg(feature = "small-rng")]
 use rand::{rngs::SmallRng, SeedableRng};
 
-use crate::canvas::{OmniCanvas, VectorCanvas};
+use crate::canvas::{LayeredCanvas, OmniCanvas};
+use crate::palette::ColorMap;
 use crate::sketchers::Sketcher;
-use barium::{Color, LineEnd, Stroke, Vec2};
+use barium::{LineEnd, Stroke, Vec2};
 
 #[derive(Clone)]
 pub struct CelestialSketcherSettings<P, S, V>
@@ -27,25 +28,28 @@ where
     pub object_velocity: V,
     /// The universal gravitational constant.
     pub g: f32,
-    /// The color to draw objects.
-    pub foreground: Color,
+    /// The color to draw objects, mapped from the order of each object, from the first (`0.0`) to the last rendered (`1.0`).
+    pub foreground: ColorMap,
     /// The number of steps to simulate.
     pub steps: usize,
     /// How much time should pass between steps.
     pub step_length: f32,
     /// Whether to render object paths as a series of regular polygons instead of as a path.
     pub render_polygon: Option<usize>,
+    /// Whether to draw each object into its own layer, named after its order (`object-1`, `object-2` and so on).
+    pub layered: bool,
 }
 
 pub struct CelestialSketcher {
     objects: Vec<CelestialObject>,
     render_count: usize,
     g: f32,
-    foreground: Color,
+    foreground: ColorMap,
     steps: usize,
     step_length: f32,
     render_polygon: Option<usize>,
-    canvas: VectorCanvas,
+    layered: bool,
+    canvas: LayeredCanvas,
 }
 
 impl CelestialSketcher {
@@ -95,7 +99,8 @@ impl CelestialSketcher {
             steps: settings.steps,
             step_length: settings.step_length,
             render_polygon: settings.render_polygon,
-            canvas: VectorCanvas::default(),
+            layered: settings.layered,
+            canvas: LayeredCanvas::new(),
         }
     }
 
@@ -119,35 +124,60 @@ impl CelestialSketcher {
         }
     }
 
+    /// The name of the layer an object is drawn into.
+    fn layer_name(&self, index: usize) -> String {
+        if self.layered {
+            format!("object-{}", index + 1)
+        } else {
+            "objects".to_string()
+        }
+    }
+
     /// Renders the path of a given number of objects.
     fn render(&mut self) {
-        self.canvas = VectorCanvas::default();
+        self.canvas = LayeredCanvas::new();
 
         if let Some(sides) = self.render_polygon {
+            let last = self.objects.len().saturating_sub(1).max(1) as f32;
+
+            // Find each object's layer once, rather than for every step.
+            let layers: Vec<usize> = (0..self.objects.len())
+                .map(|index| {
+                    let name = self.layer_name(index);
+
+                    self.canvas.layer_index(&name)
+                })
+                .collect();
+
             for i in 0..self.objects[0].path.len() {
-                for object in &self.objects {
+                for (index, object) in self.objects.iter().enumerate() {
                     let position = object.path[i];
                     let radius = (object.mass / PI).sqrt() / 5000.0;
 
-                    self.canvas.draw_regular_polygon(
-                        position,
-                        sides,
-                        radius,
-                        0.0,
-                        None,
-                        Some(self.foreground),
-                    )
+                    self.canvas.layers_mut()[layers[index]]
+                        .1
+                        .draw_regular_polygon(
+                            position,
+                            sides,
+                            radius,
+                            0.0,
+                            None,
+                            Some(self.foreground.sample(index as f32 / last)),
+                        )
                 }
             }
         } else {
+            let last = self.render_count.saturating_sub(1).max(1) as f32;
+
             for index in 0..self.render_count {
+                let layer_name = self.layer_name(index);
                 let object = &self.objects[index];
                 let radius = (object.mass / PI).sqrt() / 5000.0;
 
-                self.canvas.draw_shape(
+                self.canvas.layer_mut(&layer_name).draw_shape(
                     object.path.clone(),
                     Some(Stroke {
-                        color: self.foreground,
+                        color: self.foreground.sample(index as f32 / last),
                         width: radius * 2.0,
                         line_end: LineEnd::Round,
                     }),
@@ -157,6 +187,15 @@ impl CelestialSketcher {
         }
     }
 
+    /// The rendered canvas, which only has layers if they were asked for.
+    fn output(canvas: LayeredCanvas, layered: bool) -> OmniCanvas {
+        if layered {
+            canvas.into()
+        } else {
+            canvas.into_flattened().into()
+        }
+    }
+
     /// Simulates and renders the system.
     fn run<P: Fn(f32)>(&mut self, before_iter: P) {
         for i in 0..self.steps {
@@ -176,13 +215,13 @@ where
     fn run(&mut self, before_iter: F) -> OmniCanvas {
         self.run(before_iter);
 
-        self.canvas.clone().into()
+        Self::output(self.canvas.clone(), self.layered)
     }
 
     fn run_and_dispose(mut self, before_iter: F) -> OmniCanvas {
         self.run(before_iter);
 
-        self.canvas.into()
+        Self::output(self.canvas, self.layered)
     }
 }
 
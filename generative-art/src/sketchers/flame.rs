@@ -0,0 +1,474 @@
+use std::f32::consts::PI;
+
+use barium::{Color, Mat2, Vec2};
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, RasterCanvas, ToneMapping};
+use crate::helpers::{even_share, PixelTransform};
+
+/// A nonlinear function applied by a [FlameTransform] after its affine transform.
+/// The names and formulas follow the original fractal flame paper.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Variation {
+    Linear,
+    Sinusoidal,
+    Spherical,
+    Swirl,
+    Horseshoe,
+    Polar,
+    Handkerchief,
+    Heart,
+    Disc,
+    Spiral,
+    Hyperbolic,
+    Diamond,
+    Julia,
+    Bent,
+    Fisheye,
+    Exponential,
+    Cosine,
+    Bubble,
+    Cylinder,
+    Eyefish,
+    Tangent,
+}
+
+impl Variation {
+    /// Every variation, in declaration order.
+    pub const ALL: [Variation; 21] = [
+        Self::Linear,
+        Self::Sinusoidal,
+        Self::Spherical,
+        Self::Swirl,
+        Self::Horseshoe,
+        Self::Polar,
+        Self::Handkerchief,
+        Self::Heart,
+        Self::Disc,
+        Self::Spiral,
+        Self::Hyperbolic,
+        Self::Diamond,
+        Self::Julia,
+        Self::Bent,
+        Self::Fisheye,
+        Self::Exponential,
+        Self::Cosine,
+        Self::Bubble,
+        Self::Cylinder,
+        Self::Eyefish,
+        Self::Tangent,
+    ];
+
+    fn apply<R: Rng + ?Sized>(&self, p: Vec2, rng: &mut R) -> Vec2 {
+        let (x, y) = (p.x, p.y);
+        let r2 = p.length_squared().max(f32::EPSILON);
+        let r = r2.sqrt();
+        // The paper measures theta from the y axis.
+        let theta = x.atan2(y);
+
+        match self {
+            Self::Linear => p,
+            Self::Sinusoidal => Vec2::new(x.sin(), y.sin()),
+            Self::Spherical => p / r2,
+            Self::Swirl => Vec2::new(x * r2.sin() - y * r2.cos(), x * r2.cos() + y * r2.sin()),
+            Self::Horseshoe => Vec2::new((x - y) * (x + y), 2.0 * x * y) / r,
+            Self::Polar => Vec2::new(theta / PI, r - 1.0),
+            Self::Handkerchief => Vec2::new((theta + r).sin(), (theta - r).cos()) * r,
+            Self::Heart => Vec2::new((theta * r).sin(), -(theta * r).cos()) * r,
+            Self::Disc => Vec2::new((PI * r).sin(), (PI * r).cos()) * theta / PI,
+            Self::Spiral => Vec2::new(theta.cos() + r.sin(), theta.sin() - r.cos()) / r,
+            Self::Hyperbolic => Vec2::new(theta.sin() / r, r * theta.cos()),
+            Self::Diamond => Vec2::new(theta.sin() * r.cos(), theta.cos() * r.sin()),
+            Self::Julia => {
+                let omega = if rng.gen() { PI } else { 0.0 };
+                let angle = theta / 2.0 + omega;
+
+                Vec2::new(angle.cos(), angle.sin()) * r.sqrt()
+            }
+            Self::Bent => Vec2::new(
+                if x < 0.0 { x * 2.0 } else { x },
+                if y < 0.0 { y / 2.0 } else { y },
+            ),
+            Self::Fisheye => Vec2::new(y, x) * 2.0 / (r + 1.0),
+            Self::Exponential => Vec2::new((PI * y).cos(), (PI * y).sin()) * (x - 1.0).exp(),
+            Self::Cosine => Vec2::new((PI * x).cos() * y.cosh(), -(PI * x).sin() * y.sinh()),
+            Self::Bubble => p * 4.0 / (r2 + 4.0),
+            Self::Cylinder => Vec2::new(x.sin(), y),
+            Self::Eyefish => p * 2.0 / (r + 1.0),
+            Self::Tangent => Vec2::new(x.sin() / y.cos(), y.tan()),
+        }
+    }
+}
+
+/// One of the functions of a [FlameGenome]: an affine transform, followed by a weighted sum of variations.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlameTransform {
+    /// The relative chance of picking this transform at each iteration.
+    pub weight: f32,
+    /// The coefficients `[a, b, c, d, e, f]` of the affine transform `x' = a x + b y + c`, `y' = d x + e y + f`.
+    pub affine: [f32; 6],
+    /// The variations applied after the affine transform, with their weights.
+    pub variations: Vec<(Variation, f32)>,
+    /// The color of the transform, as red, green and blue from `0.0` to `1.0`.
+    /// Each time the transform is applied, the color of the point moves halfway towards it.
+    pub color: [f32; 3],
+}
+
+impl FlameTransform {
+    fn apply<R: Rng + ?Sized>(&self, p: Vec2, rng: &mut R) -> Vec2 {
+        let [a, b, c, d, e, f] = self.affine;
+        let p = Vec2::new(a * p.x + b * p.y + c, d * p.x + e * p.y + f);
+
+        self.variations
+            .iter()
+            .fold(Vec2::ZERO, |sum, (variation, weight)| {
+                sum + variation.apply(p, rng) * *weight
+            })
+    }
+}
+
+/// A complete description of a fractal flame, which can be saved (with the `serde` feature) and rendered again later.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlameGenome {
+    /// The transforms of the iterated function system.
+    pub transforms: Vec<FlameTransform>,
+    /// The point of the flame shown in the middle of the canvas.
+    pub center: [f32; 2],
+    /// How much the flame is magnified on the canvas.
+    pub zoom: f32,
+    /// How far the flame is rotated counter-clockwise on the canvas, in radians.
+    pub rotation: f32,
+}
+
+impl FlameGenome {
+    /// The Sierpinski triangle, as a simple starting point.
+    pub fn sierpinski() -> Self {
+        let transform = |c: f32, f: f32, color: [f32; 3]| FlameTransform {
+            weight: 1.0,
+            affine: [0.5, 0.0, c, 0.0, 0.5, f],
+            variations: vec![(Variation::Linear, 1.0)],
+            color,
+        };
+
+        Self {
+            transforms: vec![
+                transform(0.0, 0.0, [1.0, 0.2, 0.1]),
+                transform(0.5, 0.0, [0.1, 1.0, 0.2]),
+                transform(0.25, 0.5, [0.2, 0.1, 1.0]),
+            ],
+            center: [0.5, 0.5],
+            zoom: 1.8,
+            rotation: 0.0,
+        }
+    }
+
+    /// Creates a random genome with a number of transforms, each with one or two random variations.
+    pub fn random<R: Rng + ?Sized>(rng: &mut R, transforms: usize) -> Self {
+        Self {
+            transforms: (0..transforms)
+                .map(|_| {
+                    let count = rng.gen_range(1..=2);
+                    let mut variations: Vec<(Variation, f32)> = (0..count)
+                        .map(|_| {
+                            (
+                                Variation::ALL[rng.gen_range(0..Variation::ALL.len())],
+                                rng.gen_range(0.1..1.0),
+                            )
+                        })
+                        .collect();
+
+                    // Keep the variations of each transform summing to one, so points don't fly off.
+                    let total: f32 = variations.iter().map(|(_, weight)| weight).sum();
+                    for (_, weight) in &mut variations {
+                        *weight /= total;
+                    }
+
+                    FlameTransform {
+                        weight: rng.gen_range(0.2..1.0),
+                        affine: [(); 6].map(|_| rng.gen_range(-1.0..1.0)),
+                        variations,
+                        color: [(); 3].map(|_| rng.gen()),
+                    }
+                })
+                .collect(),
+            center: [0.0, 0.0],
+            zoom: 0.5,
+            rotation: 0.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FlameSketcherSettings {
+    /// The flame to render.
+    pub genome: FlameGenome,
+    /// The total number of points to plot.
+    pub points: usize,
+    /// The width of the output image.
+    pub width: usize,
+    /// The height of the output image.
+    pub height: usize,
+    /// Points are plotted on a grid this many times finer than the output image, which is then averaged down for anti-aliasing.
+    pub supersample: usize,
+    /// How densities are mapped to the opacity of each pixel. Flames usually look best with [log](ToneMapping::log) set.
+    pub tone_mapping: ToneMapping,
+    /// The color behind the flame.
+    pub background: Color,
+}
+
+/// The state of one of the separate chains of points followed by a [FlameSketcher].
+struct Orbit {
+    point: Vec2,
+    color: [f32; 3],
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+/// Renders fractal flames: iterated function systems with nonlinear variations, colored by the transforms each point passed through.
+pub struct FlameSketcher {
+    settings: FlameSketcherSettings,
+    /// The hit count and the sum of the colors of the hits of each subpixel.
+    histogram: Vec<[f32; 4]>,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+impl FlameSketcher {
+    /// The number of separate orbits, which can be followed in parallel.
+    const ORBITS: usize = 16;
+    /// The number of times progress is reported.
+    const BATCHES: usize = 100;
+    /// The number of points skipped at the start of each orbit, before it settles onto the flame.
+    const WARM_UP: usize = 20;
+
+    pub fn new(settings: FlameSketcherSettings, #[cfg(feature = "small-rng")] seed: u64) -> Self {
+        Self {
+            settings,
+            histogram: Vec::new(),
+            #[cfg(feature = "small-rng")]
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    fn supersample(&self) -> usize {
+        self.settings.supersample.max(1)
+    }
+
+    /// Picks a transform in proportion to the weights.
+    fn choose(cumulative_weights: &[f32], rng: &mut (impl Rng + ?Sized)) -> usize {
+        let total = cumulative_weights.last().copied().unwrap_or(0.0);
+        let choice = rng.gen::<f32>() * total;
+
+        cumulative_weights
+            .iter()
+            .position(|&weight| choice < weight)
+            .unwrap_or(cumulative_weights.len() - 1)
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        let supersample = self.supersample();
+        let width = self.settings.width * supersample;
+        let height = self.settings.height * supersample;
+
+        self.histogram = vec![[0.0; 4]; width * height];
+
+        let genome = &self.settings.genome;
+
+        if genome.transforms.is_empty() {
+            return;
+        }
+
+        let cumulative_weights: Vec<f32> = genome
+            .transforms
+            .iter()
+            .scan(0.0, |total, transform| {
+                *total += transform.weight.max(0.0);
+                Some(*total)
+            })
+            .collect();
+
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let rng = &mut self.rng;
+
+        let mut orbits: Vec<Orbit> = (0..Self::ORBITS)
+            .map(|_| Orbit {
+                point: Vec2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)),
+                color: [0.5; 3],
+                #[cfg(feature = "small-rng")]
+                rng: SmallRng::seed_from_u64(rng.gen()),
+            })
+            .collect();
+
+        let step = |orbit: &mut Orbit, rng: &mut dyn rand::RngCore| {
+            let transform = &genome.transforms[Self::choose(&cumulative_weights, rng)];
+
+            orbit.point = transform.apply(orbit.point, rng);
+
+            for (color, target) in orbit.color.iter_mut().zip(transform.color) {
+                *color = (*color + target) / 2.0;
+            }
+
+            // Points that escape to infinity are restarted somewhere random, and not plotted until they settle again.
+            if !orbit.point.is_finite() {
+                orbit.point = Vec2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+
+                false
+            } else {
+                true
+            }
+        };
+
+        let camera_rotation = Mat2::from_angle(genome.rotation);
+        let center = Vec2::from(genome.center);
+        let transform = PixelTransform::new(width, height);
+
+        let points = self.settings.points;
+
+        // Each orbit plots its share of the points in each batch.
+        let follow = |orbit: &mut Orbit, share: usize, warm_up: usize| -> Vec<(Vec2, [f32; 3])> {
+            #[cfg(feature = "thread-rng")]
+            let mut rng = rand::thread_rng();
+            #[cfg(feature = "small-rng")]
+            let mut rng = orbit.rng.clone();
+
+            let mut settling = warm_up;
+            let mut hits = Vec::with_capacity(share);
+
+            for _ in 0..share + warm_up {
+                if !step(orbit, &mut rng) {
+                    settling = Self::WARM_UP;
+                } else if settling > 0 {
+                    settling -= 1;
+                } else {
+                    let p = camera_rotation.mul_vec2(orbit.point - center) * genome.zoom;
+
+                    hits.push((transform.invert(p), orbit.color));
+                }
+            }
+
+            #[cfg(feature = "small-rng")]
+            {
+                orbit.rng = rng;
+            }
+
+            hits
+        };
+
+        for batch in 0..Self::BATCHES {
+            before_iter(batch as f32 / Self::BATCHES as f32);
+
+            let warm_up = if batch == 0 { Self::WARM_UP } else { 0 };
+            let share_of = |orbit: usize| {
+                even_share(
+                    points,
+                    Self::ORBITS * Self::BATCHES,
+                    batch * Self::ORBITS + orbit,
+                )
+            };
+
+            #[cfg(feature = "parallel")]
+            let hits: Vec<Vec<(Vec2, [f32; 3])>> = orbits
+                .par_iter_mut()
+                .enumerate()
+                .map(|(i, orbit)| follow(orbit, share_of(i), warm_up))
+                .collect();
+            #[cfg(not(feature = "parallel"))]
+            let hits: Vec<Vec<(Vec2, [f32; 3])>> = orbits
+                .iter_mut()
+                .enumerate()
+                .map(|(i, orbit)| follow(orbit, share_of(i), warm_up))
+                .collect();
+
+            for (p, [r, g, b]) in hits.into_iter().flatten() {
+                if p.x >= 0.0 && p.y >= 0.0 && p.x < width as f32 && p.y < height as f32 {
+                    let bucket = &mut self.histogram[p.y as usize * width + p.x as usize];
+
+                    bucket[0] += 1.0;
+                    bucket[1] += r;
+                    bucket[2] += g;
+                    bucket[3] += b;
+                }
+            }
+        }
+    }
+
+    /// Averages the histogram down to the output size and tone maps it.
+    fn render(&self) -> RasterCanvas {
+        let supersample = self.supersample();
+        let width = self.settings.width;
+        let height = self.settings.height;
+        let mut canvas = RasterCanvas::new(width, height);
+
+        if self.histogram.len() != width * height * supersample * supersample {
+            return canvas;
+        }
+
+        let pixels: Vec<[f32; 4]> = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let mut sum = [0.0; 4];
+
+                for sy in 0..supersample {
+                    for sx in 0..supersample {
+                        let bucket = self.histogram
+                            [(y * supersample + sy) * width * supersample + x * supersample + sx];
+
+                        for (total, value) in sum.iter_mut().zip(bucket) {
+                            *total += value;
+                        }
+                    }
+                }
+
+                sum
+            })
+            .collect();
+
+        let max = pixels.iter().map(|pixel| pixel[0]).fold(0.0, f32::max);
+        let background = self.settings.background;
+
+        for (i, [count, r, g, b]) in pixels.into_iter().enumerate() {
+            let alpha = self.settings.tone_mapping.apply(count, max);
+            let count = count.max(f32::EPSILON);
+
+            canvas.set_pixel(
+                i % width,
+                i / width,
+                Color::new(
+                    background.r() + (r / count - background.r()) * alpha,
+                    background.g() + (g / count - background.g()) * alpha,
+                    background.b() + (b / count - background.b()) * alpha,
+                    background.a() + (1.0 - background.a()) * alpha,
+                ),
+            );
+        }
+
+        canvas
+    }
+}
+
+impl<P> Sketcher<P> for FlameSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.render().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.render().into()
+    }
+}
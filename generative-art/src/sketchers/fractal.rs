@@ -0,0 +1,348 @@
+use std::ops::{Add, Mul, Sub};
+
+use barium::{Color, Vec2};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, RasterCanvas};
+use crate::helpers::PixelTransform;
+use crate::palette::ColorMap;
+
+/// The fractal rendered by a [FractalSketcher]. Complex numbers are written `[re, im]`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FractalKind {
+    /// Iterates `z = z^2 + c` from zero, where `c` is the point.
+    Mandelbrot,
+    /// Iterates `z = z^2 + c` from the point, for a fixed `c`.
+    Julia { c: [f64; 2] },
+    /// Iterates `z = (|re z| + i |im z|)^2 + c` from zero, where `c` is the point.
+    BurningShip,
+    /// Finds the roots of `z^degree - 1` with Newton's method, starting from the point.
+    /// Each root gets its own slice of the colormap, shaded by how quickly it was reached.
+    Newton { degree: u32 },
+}
+
+/// A shape that the orbit of each point is measured against, for [FractalColoring::OrbitTrap].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OrbitTrap {
+    Point {
+        center: [f64; 2],
+    },
+    Circle {
+        center: [f64; 2],
+        radius: f64,
+    },
+    /// The horizontal and vertical lines through a point.
+    Cross {
+        center: [f64; 2],
+    },
+}
+
+impl OrbitTrap {
+    fn distance(&self, [re, im]: [f64; 2]) -> f64 {
+        match *self {
+            Self::Point { center } => (re - center[0]).hypot(im - center[1]),
+            Self::Circle { center, radius } => {
+                ((re - center[0]).hypot(im - center[1]) - radius).abs()
+            }
+            Self::Cross { center } => (re - center[0]).abs().min((im - center[1]).abs()),
+        }
+    }
+}
+
+/// How a [FractalSketcher] picks a position on the colormap for each point.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FractalColoring {
+    /// The number of iterations before the point escaped, with the fractional part estimated from how far it escaped,
+    /// so that there are no visible bands.
+    Smooth,
+    /// The whole number of iterations before the point escaped.
+    Bands,
+    /// The closest the orbit of the point came to a trap. Points that came within `size` of it are placed along the colormap
+    /// by distance, from the end at the trap to the start at `size` away. Other points get the interior color.
+    OrbitTrap { trap: OrbitTrap, size: f64 },
+}
+
+#[derive(Clone)]
+pub struct FractalSketcherSettings {
+    /// The fractal to render.
+    pub kind: FractalKind,
+    /// The width of the output image.
+    pub width: usize,
+    /// The height of the output image.
+    pub height: usize,
+    /// The point of the complex plane shown in the middle of the canvas.
+    pub center: [f64; 2],
+    /// How much the complex plane is magnified. At `1.0`, the minor dimension of the image spans from `-1` to `1`.
+    pub zoom: f64,
+    /// The most times each point is iterated.
+    pub max_iterations: usize,
+    /// Points further than this from the origin are considered to have escaped. Larger values give smoother coloring.
+    /// For [FractalKind::Newton], points closer than the reciprocal of this to a root are considered to have converged.
+    pub escape_radius: f64,
+    /// How to pick the color of each point.
+    pub coloring: FractalColoring,
+    /// Maps points to colors, from the first iteration (`0.0`) to the last (`1.0`), or as set by the coloring.
+    pub colormap: ColorMap,
+    /// If set, the colormap repeats every this many iterations instead of being stretched over all of them.
+    pub color_cycle: Option<f32>,
+    /// The color of points that never escape (or never converge).
+    pub interior: Color,
+    /// Iterate in double precision, which is slower but allows much deeper zooms.
+    pub double_precision: bool,
+}
+
+/// The operations needed to iterate a fractal, so it can be done in either precision.
+trait Real: Copy + PartialOrd + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> {
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn abs(self) -> Self;
+}
+
+impl Real for f32 {
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn abs(self) -> Self {
+        f32::abs(self)
+    }
+}
+
+impl Real for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+}
+
+/// Renders escape-time fractals, like the Mandelbrot set, Julia sets, the Burning Ship and Newton fractals.
+pub struct FractalSketcher {
+    settings: FractalSketcherSettings,
+    canvas: RasterCanvas,
+}
+
+impl FractalSketcher {
+    /// The number of times progress is reported.
+    const BATCHES: usize = 100;
+
+    pub fn new(settings: FractalSketcherSettings) -> Self {
+        Self {
+            canvas: RasterCanvas::new(settings.width, settings.height),
+            settings,
+        }
+    }
+
+    /// Iterates a point, returning the (possibly fractional) number of iterations it took to escape and the closest it came to the trap.
+    /// The iteration count is `None` for points that never escaped.
+    fn iterate<T: Real>(&self, point: [f64; 2]) -> (Option<f64>, f64) {
+        let settings = &self.settings;
+        let trap = match settings.coloring {
+            FractalColoring::OrbitTrap { trap, .. } => Some(trap),
+            _ => None,
+        };
+
+        let escape = settings.escape_radius.max(2.0);
+        let escape_squared = T::from_f64(escape * escape);
+
+        let [pr, pi] = point.map(T::from_f64);
+        let (mut zr, mut zi, cr, ci) = match settings.kind {
+            FractalKind::Mandelbrot | FractalKind::BurningShip => {
+                (T::from_f64(0.0), T::from_f64(0.0), pr, pi)
+            }
+            FractalKind::Julia { c } => (pr, pi, T::from_f64(c[0]), T::from_f64(c[1])),
+            FractalKind::Newton { .. } => (pr, pi, T::from_f64(0.0), T::from_f64(0.0)),
+        };
+
+        let mut trap_distance = f64::INFINITY;
+
+        for i in 0..settings.max_iterations {
+            match settings.kind {
+                FractalKind::Newton { degree } => {
+                    let degree = degree.max(2);
+                    let (re, im) = (zr.to_f64(), zi.to_f64());
+
+                    // z^(degree - 1), so that z^degree is one more multiplication.
+                    let (mut qr, mut qi) = (1.0, 0.0);
+                    for _ in 0..degree - 1 {
+                        (qr, qi) = (qr * re - qi * im, qr * im + qi * re);
+                    }
+
+                    let (fr, fi) = (qr * re - qi * im - 1.0, qr * im + qi * re);
+                    let (dr, di) = (qr * degree as f64, qi * degree as f64);
+                    let denominator = dr * dr + di * di;
+
+                    if denominator == 0.0 {
+                        return (None, trap_distance);
+                    }
+
+                    zr = zr - T::from_f64((fr * dr + fi * di) / denominator);
+                    zi = zi - T::from_f64((fi * dr - fr * di) / denominator);
+
+                    if let Some(trap) = trap {
+                        trap_distance =
+                            trap_distance.min(trap.distance([zr.to_f64(), zi.to_f64()]));
+                    }
+
+                    if fr.hypot(fi) < 1.0 / escape {
+                        let angle = zi
+                            .to_f64()
+                            .atan2(zr.to_f64())
+                            .rem_euclid(std::f64::consts::TAU);
+                        let root =
+                            (angle / std::f64::consts::TAU * degree as f64).round() as u32 % degree;
+
+                        // Encode the root in the whole part, and the speed of convergence in the fractional part.
+                        let speed = 1.0 - i as f64 / settings.max_iterations as f64;
+
+                        return (Some(root as f64 + speed * 0.999), trap_distance);
+                    }
+                }
+                kind => {
+                    if let FractalKind::BurningShip = kind {
+                        zr = zr.abs();
+                        zi = zi.abs();
+                    }
+
+                    let next_r = zr * zr - zi * zi + cr;
+                    zi = T::from_f64(2.0) * zr * zi + ci;
+                    zr = next_r;
+
+                    if let Some(trap) = trap {
+                        trap_distance =
+                            trap_distance.min(trap.distance([zr.to_f64(), zi.to_f64()]));
+                    }
+
+                    let magnitude_squared = zr * zr + zi * zi;
+
+                    if magnitude_squared > escape_squared {
+                        let iterations = match settings.coloring {
+                            FractalColoring::Smooth => {
+                                let log_magnitude = magnitude_squared.to_f64().ln() / 2.0;
+
+                                i as f64 + 1.0 - (log_magnitude.ln() / 2f64.ln())
+                                    + escape.ln().ln() / 2f64.ln()
+                            }
+                            _ => i as f64,
+                        };
+
+                        return (Some(iterations.max(0.0)), trap_distance);
+                    }
+                }
+            }
+        }
+
+        (None, trap_distance)
+    }
+
+    /// Picks the color of a point.
+    fn color(&self, point: [f64; 2]) -> Color {
+        let settings = &self.settings;
+
+        let (iterations, trap_distance) = if settings.double_precision {
+            self.iterate::<f64>(point)
+        } else {
+            self.iterate::<f32>(point)
+        };
+
+        let t = match settings.coloring {
+            FractalColoring::OrbitTrap { size, .. } => {
+                if trap_distance >= size {
+                    return settings.interior;
+                }
+
+                1.0 - (trap_distance / size.max(f64::EPSILON)) as f32
+            }
+            _ => match (iterations, settings.kind) {
+                (None, _) => return settings.interior,
+                (Some(root), FractalKind::Newton { degree }) => {
+                    let speed = root.fract() as f32;
+                    let root = root.trunc() as f32;
+
+                    match settings.color_cycle {
+                        Some(cycle) => {
+                            (root + (speed * settings.max_iterations as f32 / cycle).fract())
+                                / degree.max(2) as f32
+                        }
+                        None => (root + speed) / degree.max(2) as f32,
+                    }
+                }
+                (Some(iterations), _) => match settings.color_cycle {
+                    Some(cycle) => (iterations as f32 / cycle.max(f32::EPSILON)).fract(),
+                    None => iterations as f32 / settings.max_iterations.max(1) as f32,
+                },
+            },
+        };
+
+        settings.colormap.sample(t)
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        let width = self.settings.width;
+        let height = self.settings.height;
+
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let transform = PixelTransform::new(width, height);
+        let center = self.settings.center;
+        let zoom = self.settings.zoom.max(f64::EPSILON);
+
+        let rows_per_batch = height.div_ceil(Self::BATCHES);
+        let mut pixels = vec![Color::transparent(); width * height];
+
+        for (batch, chunk) in pixels.chunks_mut(width * rows_per_batch).enumerate() {
+            before_iter((batch * rows_per_batch) as f32 / height as f32);
+
+            let render_row = |(row, pixels): (usize, &mut [Color])| {
+                let y = batch * rows_per_batch + row;
+
+                for (x, pixel) in pixels.iter_mut().enumerate() {
+                    let p = transform.apply(Vec2::new(x as f32 + 0.5, y as f32 + 0.5));
+
+                    *pixel =
+                        self.color([center[0] + p.x as f64 / zoom, center[1] + p.y as f64 / zoom]);
+                }
+            };
+
+            #[cfg(feature = "parallel")]
+            chunk.par_chunks_mut(width).enumerate().for_each(render_row);
+            #[cfg(not(feature = "parallel"))]
+            chunk.chunks_mut(width).enumerate().for_each(render_row);
+        }
+
+        for (i, color) in pixels.into_iter().enumerate() {
+            self.canvas.set_pixel(i % width, i / width, color);
+        }
+    }
+}
+
+impl<P> Sketcher<P> for FractalSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
@@ -0,0 +1,476 @@
+use std::f32::consts::PI;
+use std::fmt::Write;
+
+use barium::{Color, LineEnd, Stroke, Vec2};
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::helpers::{variable_width_outline, PixelTransform};
+
+/// A classic ramp of characters, from lightest to darkest.
+pub const DEFAULT_RAMP: &str = " .:-=+*#%@";
+
+/// A symbol a [GlyphSketcher] can place in a cell.
+#[derive(Clone)]
+pub struct Glyph {
+    /// The character used in text and HTML output.
+    pub character: char,
+    /// The shapes used in vector output and for structural matching.
+    /// Glyphs are drawn in the space -1..1 (with y pointing up), which is stretched to fill the cell.
+    pub shape: VectorCanvas,
+}
+
+impl Glyph {
+    /// Creates glyphs for each character of a ramp, using simple built-in line drawings.
+    ///
+    /// Only the characters ` .:-=+*#%@oOx/\|` have built-in drawings. Any others are given an empty shape,
+    /// so they still work for text and HTML output.
+    pub fn ramp(characters: &str, stroke_width: f32) -> Vec<Self> {
+        characters
+            .chars()
+            .map(|character| Self {
+                character,
+                shape: builtin_shape(character, stroke_width),
+            })
+            .collect()
+    }
+}
+
+/// How a [GlyphSketcher] picks the glyph for each cell.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GlyphMatching {
+    /// By the brightness of the cell. The glyphs must be ordered from lightest to darkest.
+    Brightness,
+    /// By comparing the pattern of light and dark within the cell to the shape of each glyph.
+    Structure,
+}
+
+#[derive(Clone)]
+pub struct GlyphSketcherSettings {
+    /// The glyphs to pick from.
+    pub glyphs: Vec<Glyph>,
+    /// The width of each cell, in pixels of the input image.
+    pub cell_size: usize,
+    /// The height of each cell relative to its width. Most fonts are about twice as tall as they are wide.
+    pub aspect_ratio: f32,
+    /// How to pick the glyph for each cell.
+    pub matching: GlyphMatching,
+    /// By default, dark glyphs are picked for dark areas. This picks them for light areas instead.
+    pub invert_brightness: bool,
+    /// Color each glyph with the average color of its cell, instead of the glyph's own colors.
+    pub colored: bool,
+}
+
+/// Maps cells of an image to characters or glyphs, for ASCII art and other symbol mosaics.
+///
+/// Besides running it as a [Sketcher] for vector output, the result can be rendered as [text](Self::to_text) or [HTML](Self::to_html).
+pub struct GlyphSketcher {
+    settings: GlyphSketcherSettings,
+    transform: PixelTransform,
+    input_image: RasterCanvas,
+    canvas: VectorCanvas,
+}
+
+impl GlyphSketcher {
+    /// The resolution (per side) of the patterns compared by [GlyphMatching::Structure].
+    const PATTERN_SIZE: usize = 6;
+
+    pub fn new(input_image: RasterCanvas, settings: GlyphSketcherSettings) -> Self {
+        Self {
+            settings,
+            transform: PixelTransform::new(input_image.width(), input_image.height()),
+            input_image,
+            canvas: VectorCanvas::default(),
+        }
+    }
+
+    /// Consumes the sketcher and returns the input image.
+    pub fn take_input(self) -> RasterCanvas {
+        self.input_image
+    }
+
+    /// The size of each cell, in pixels.
+    fn cell_size(&self) -> (usize, usize) {
+        let width = self.settings.cell_size.max(1);
+        let height = ((width as f32 * self.settings.aspect_ratio).round() as usize).max(1);
+
+        (width, height)
+    }
+
+    /// The number of columns and rows of cells.
+    fn grid_size(&self) -> (usize, usize) {
+        let (cell_width, cell_height) = self.cell_size();
+
+        (
+            self.input_image.width() / cell_width,
+            self.input_image.height() / cell_height,
+        )
+    }
+
+    /// Gets the average color of a rectangle of the input image (in pixel space).
+    fn average_color(&self, min: Vec2, max: Vec2) -> Color {
+        self.input_image
+            .get_average_color(&[min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)])
+            .unwrap_or_else(Color::transparent)
+    }
+
+    /// How dark a color is, accounting for [invert_brightness](GlyphSketcherSettings::invert_brightness).
+    fn darkness(&self, color: Color) -> f32 {
+        let brightness = (color.r() + color.g() + color.b()) * color.a() / 3.0;
+
+        if self.settings.invert_brightness {
+            brightness
+        } else {
+            1.0 - brightness
+        }
+    }
+
+    /// Picks the glyph (and color) for every cell, row by row.
+    fn choose_glyphs<P: Fn(f32)>(&self, before_iter: P) -> Vec<(usize, Color)> {
+        let (columns, rows) = self.grid_size();
+        let (cell_width, cell_height) = self.cell_size();
+        let cell = Vec2::new(cell_width as f32, cell_height as f32);
+
+        let glyph_count = self.settings.glyphs.len();
+        if glyph_count == 0 {
+            return Vec::new();
+        }
+
+        let patterns = match self.settings.matching {
+            GlyphMatching::Brightness => Vec::new(),
+            GlyphMatching::Structure => glyph_patterns(&self.settings.glyphs, Self::PATTERN_SIZE),
+        };
+
+        let mut chosen = Vec::with_capacity(columns * rows);
+
+        for row in 0..rows {
+            before_iter(row as f32 / rows as f32);
+
+            for column in 0..columns {
+                let min = Vec2::new(column as f32, row as f32) * cell;
+                let color = self.average_color(min, min + cell);
+
+                let glyph = match self.settings.matching {
+                    GlyphMatching::Brightness => {
+                        let darkness = self.darkness(color).clamp(0.0, 1.0);
+
+                        ((darkness * glyph_count as f32) as usize).min(glyph_count - 1)
+                    }
+                    GlyphMatching::Structure => {
+                        let step = cell / Self::PATTERN_SIZE as f32;
+
+                        let pattern: Vec<f32> = (0..Self::PATTERN_SIZE * Self::PATTERN_SIZE)
+                            .map(|i| {
+                                let block = Vec2::new(
+                                    (i % Self::PATTERN_SIZE) as f32,
+                                    (i / Self::PATTERN_SIZE) as f32,
+                                );
+                                let block_min = min + block * step;
+
+                                // Blocks smaller than a pixel may not cover any pixel centers, so fall back to the nearest pixel.
+                                let block_center = block_min + step / 2.0;
+                                let block_color = self
+                                    .input_image
+                                    .get_average_color(&[
+                                        block_min,
+                                        Vec2::new(block_min.x + step.x, block_min.y),
+                                        block_min + step,
+                                        Vec2::new(block_min.x, block_min.y + step.y),
+                                    ])
+                                    .unwrap_or_else(|| {
+                                        self.input_image.get_pixel(
+                                            block_center.x as usize,
+                                            block_center.y as usize,
+                                        )
+                                    });
+
+                                self.darkness(block_color)
+                            })
+                            .collect();
+
+                        patterns
+                            .iter()
+                            .map(|glyph_pattern| {
+                                glyph_pattern
+                                    .iter()
+                                    .zip(&pattern)
+                                    .map(|(a, b)| (a - b) * (a - b))
+                                    .sum::<f32>()
+                            })
+                            .enumerate()
+                            .fold((0, f32::INFINITY), |best, (i, error)| {
+                                if error < best.1 {
+                                    (i, error)
+                                } else {
+                                    best
+                                }
+                            })
+                            .0
+                    }
+                };
+
+                chosen.push((glyph, color));
+            }
+        }
+
+        chosen
+    }
+
+    /// Renders the image as plain text, with one line per row of cells.
+    pub fn to_text(&self) -> String {
+        let (columns, _) = self.grid_size();
+
+        let mut text = String::new();
+
+        for (i, (glyph, _)) in self.choose_glyphs(|_| ()).into_iter().enumerate() {
+            if i > 0 && i % columns == 0 {
+                text.push('\n');
+            }
+
+            text.push(self.settings.glyphs[glyph].character);
+        }
+
+        text
+    }
+
+    /// Renders the image as an HTML `<pre>` element.
+    /// If [colored](GlyphSketcherSettings::colored) is set, each character is wrapped in a `<span>` with the color of its cell.
+    pub fn to_html(&self) -> String {
+        let (columns, _) = self.grid_size();
+
+        let mut html = String::from("<pre style=\"font-family: monospace; line-height: 1;\">");
+
+        for (i, (glyph, color)) in self.choose_glyphs(|_| ()).into_iter().enumerate() {
+            if i > 0 && i % columns == 0 {
+                html.push('\n');
+            }
+
+            let character = match self.settings.glyphs[glyph].character {
+                '&' => "&amp;".to_string(),
+                '<' => "&lt;".to_string(),
+                '>' => "&gt;".to_string(),
+                c => c.to_string(),
+            };
+
+            if self.settings.colored {
+                let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0) as u8;
+
+                let _ = write!(
+                    html,
+                    "<span style=\"color: #{:02x}{:02x}{:02x}\">{}</span>",
+                    channel(color.r()),
+                    channel(color.g()),
+                    channel(color.b()),
+                    character
+                );
+            } else {
+                html.push_str(&character);
+            }
+        }
+
+        html.push_str("</pre>");
+
+        html
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        let (columns, _) = self.grid_size();
+        let (cell_width, cell_height) = self.cell_size();
+        let cell = Vec2::new(cell_width as f32, cell_height as f32);
+
+        for (i, (glyph, color)) in self.choose_glyphs(before_iter).into_iter().enumerate() {
+            let center = (Vec2::new((i % columns) as f32, (i / columns) as f32) + 0.5) * cell;
+
+            // Glyphs have y pointing up, while pixel space has it pointing down.
+            let to_pixel = |p: Vec2| center + Vec2::new(p.x, -p.y) * cell / 2.0;
+            let width_scale = (self.transform.apply(to_pixel(Vec2::X))
+                - self.transform.apply(to_pixel(Vec2::ZERO)))
+            .length();
+
+            for shape in self.settings.glyphs[glyph].shape.as_raw() {
+                let recolor = |original: Color| {
+                    if self.settings.colored {
+                        Color::new(color.r(), color.g(), color.b(), original.a())
+                    } else {
+                        original
+                    }
+                };
+
+                self.canvas.draw_shape(
+                    shape
+                        .points
+                        .iter()
+                        .map(|p| self.transform.apply(to_pixel(*p)))
+                        .collect(),
+                    shape.stroke.map(|stroke| Stroke {
+                        color: recolor(stroke.color),
+                        width: stroke.width * width_scale,
+                        line_end: stroke.line_end,
+                    }),
+                    shape.fill.map(recolor),
+                );
+            }
+        }
+    }
+}
+
+impl<P> Sketcher<P> for GlyphSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
+
+/// Rasterizes each glyph into a square pattern of coverage (0..1), normalized so the densest block of any glyph is `1.0`.
+fn glyph_patterns(glyphs: &[Glyph], size: usize) -> Vec<Vec<f32>> {
+    // Rasterize at a higher resolution than the pattern, so thin lines aren't lost.
+    const SUPERSAMPLING: usize = 4;
+    let resolution = size * SUPERSAMPLING;
+    let scale = resolution as f32 / 2.0;
+
+    let to_pixel = |p: Vec2| Vec2::new(p.x + 1.0, 1.0 - p.y) * scale;
+
+    let mut patterns: Vec<Vec<f32>> = glyphs
+        .iter()
+        .map(|glyph| {
+            let mut raster = RasterCanvas::new(resolution, resolution);
+
+            for shape in glyph.shape.as_raw() {
+                let points: Vec<Vec2> = shape.points.iter().map(|p| to_pixel(*p)).collect();
+
+                if shape.fill.is_some() {
+                    raster.fill_polygon(&points, Color::black());
+                }
+
+                if let Some(stroke) = shape.stroke {
+                    let widths = vec![stroke.width * scale; points.len()];
+                    let outline =
+                        variable_width_outline(&points, &widths, stroke.line_end == LineEnd::Round);
+
+                    raster.fill_polygon(&outline, Color::black());
+                }
+            }
+
+            (0..size * size)
+                .map(|i| {
+                    let (block_x, block_y) = (i % size, i / size);
+
+                    let mut coverage = 0.0;
+                    for y in block_y * SUPERSAMPLING..(block_y + 1) * SUPERSAMPLING {
+                        for x in block_x * SUPERSAMPLING..(block_x + 1) * SUPERSAMPLING {
+                            coverage += raster.get_pixel(x, y).a();
+                        }
+                    }
+
+                    coverage / (SUPERSAMPLING * SUPERSAMPLING) as f32
+                })
+                .collect()
+        })
+        .collect();
+
+    let densest = patterns
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f32::EPSILON, f32::max);
+
+    for coverage in patterns.iter_mut().flatten() {
+        *coverage /= densest;
+    }
+
+    patterns
+}
+
+/// Creates the built-in line drawing of a character, or an empty canvas if there is none.
+fn builtin_shape(character: char, stroke_width: f32) -> VectorCanvas {
+    let mut canvas = VectorCanvas::default();
+
+    let stroke = Some(Stroke {
+        color: Color::black(),
+        width: stroke_width,
+        line_end: LineEnd::Round,
+    });
+
+    let mut line = |points: &[(f32, f32)]| {
+        canvas.draw_shape(
+            points.iter().map(|&(x, y)| Vec2::new(x, y)).collect(),
+            stroke,
+            None,
+        )
+    };
+
+    let circle = |center: (f32, f32), radius: f32| -> Vec<(f32, f32)> {
+        (0..=16)
+            .map(|i| {
+                let theta = i as f32 / 16.0 * 2.0 * PI;
+
+                (
+                    center.0 + theta.cos() * radius,
+                    center.1 + theta.sin() * radius,
+                )
+            })
+            .collect()
+    };
+
+    // Dots are drawn as tiny circles, so they have some size.
+    let dot = |center: (f32, f32)| circle(center, stroke_width / 2.0);
+
+    match character {
+        '.' => line(&dot((0.0, -0.6))),
+        ':' => {
+            line(&dot((0.0, 0.3)));
+            line(&dot((0.0, -0.5)));
+        }
+        '-' => line(&[(-0.5, 0.0), (0.5, 0.0)]),
+        '=' => {
+            line(&[(-0.5, 0.25), (0.5, 0.25)]);
+            line(&[(-0.5, -0.25), (0.5, -0.25)]);
+        }
+        '+' => {
+            line(&[(-0.5, 0.0), (0.5, 0.0)]);
+            line(&[(0.0, -0.5), (0.0, 0.5)]);
+        }
+        '*' => {
+            line(&[(0.0, -0.5), (0.0, 0.5)]);
+            line(&[(-0.45, -0.25), (0.45, 0.25)]);
+            line(&[(-0.45, 0.25), (0.45, -0.25)]);
+        }
+        '#' => {
+            line(&[(-0.3, -0.7), (-0.3, 0.7)]);
+            line(&[(0.3, -0.7), (0.3, 0.7)]);
+            line(&[(-0.7, -0.3), (0.7, -0.3)]);
+            line(&[(-0.7, 0.3), (0.7, 0.3)]);
+        }
+        '%' => {
+            line(&[(-0.6, -0.7), (0.6, 0.7)]);
+            line(&circle((-0.4, 0.45), 0.18));
+            line(&circle((0.4, -0.45), 0.18));
+        }
+        '@' => {
+            line(&circle((0.0, 0.0), 0.7));
+            line(&circle((0.0, 0.0), 0.3));
+        }
+        'o' => line(&circle((0.0, -0.2), 0.4)),
+        'O' => line(&circle((0.0, 0.0), 0.7)),
+        'x' => {
+            line(&[(-0.45, -0.6), (0.45, 0.2)]);
+            line(&[(-0.45, 0.2), (0.45, -0.6)]);
+        }
+        '/' => line(&[(-0.5, -0.8), (0.5, 0.8)]),
+        '\\' => line(&[(-0.5, 0.8), (0.5, -0.8)]),
+        '|' => line(&[(0.0, -0.8), (0.0, 0.8)]),
+        _ => (),
+    }
+
+    canvas
+}
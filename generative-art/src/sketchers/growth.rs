@@ -0,0 +1,211 @@
+use std::f32::consts::PI;
+
+use barium::{Stroke, Vec2};
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, VectorCanvas};
+use crate::helpers::SpatialHash;
+use crate::palette::{map_stroke, ColorMap};
+
+#[derive(Clone)]
+pub struct GrowthSketcherSettings {
+    /// The number of nodes in the initial circle.
+    pub initial_nodes: usize,
+    /// The radius of the initial circle.
+    pub initial_radius: f32,
+    /// The number of steps to simulate.
+    pub steps: usize,
+    /// How much time should pass between steps.
+    pub step_length: f32,
+    /// Nodes push away other nodes closer than this.
+    pub repulsion_radius: f32,
+    /// How strongly nodes push each other away.
+    pub repulsion: f32,
+    /// How strongly nodes are pulled towards the middle of their neighbours along the curve, which keeps it smooth.
+    pub attraction: f32,
+    /// Edges longer than this are split in two.
+    pub max_edge_length: f32,
+    /// The chance per step that each edge is split regardless of its length, which makes growth less even.
+    pub growth_rate: f32,
+    /// Edges are no longer split once the curve has this many nodes.
+    pub max_nodes: usize,
+    /// If set, the curve is also drawn every this many steps, for layered output.
+    pub snapshot_interval: Option<usize>,
+    /// The stroke to draw the curve with.
+    pub stroke: Stroke,
+    /// If set, colors each snapshot by its age, from the first (`0.0`) to the final curve (`1.0`), instead of the stroke color.
+    pub stroke_colors: Option<ColorMap>,
+}
+
+/// Grows a closed curve into coral-like forms, by having its nodes push each other away and splitting its edges as they stretch.
+pub struct GrowthSketcher {
+    settings: GrowthSketcherSettings,
+    nodes: Vec<Vec2>,
+    snapshots: Vec<Vec<Vec2>>,
+    canvas: VectorCanvas,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+impl GrowthSketcher {
+    pub fn new(settings: GrowthSketcherSettings, #[cfg(feature = "small-rng")] seed: u64) -> Self {
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let mut rng = SmallRng::seed_from_u64(seed);
+
+        // A little jitter breaks the symmetry of the circle, so it doesn't grow evenly.
+        let jitter = settings.repulsion_radius * 0.1;
+
+        let nodes = (0..settings.initial_nodes)
+            .map(|i| {
+                let angle = i as f32 / settings.initial_nodes as f32 * 2.0 * PI;
+
+                Vec2::new(angle.cos(), angle.sin()) * settings.initial_radius
+                    + Vec2::new(
+                        rng.gen_range(-1.0..=1.0) * jitter,
+                        rng.gen_range(-1.0..=1.0) * jitter,
+                    )
+            })
+            .collect();
+
+        Self {
+            settings,
+            nodes,
+            snapshots: Vec::new(),
+            canvas: VectorCanvas::default(),
+            #[cfg(feature = "small-rng")]
+            rng,
+        }
+    }
+
+    /// Computes the next step of the simulation.
+    fn step(&mut self) {
+        let nodes = &self.nodes;
+        let count = nodes.len();
+
+        if count < 3 {
+            return;
+        }
+
+        let radius = self.settings.repulsion_radius;
+        let hash = SpatialHash::new(nodes, radius);
+
+        #[cfg(feature = "parallel")]
+        let indices = (0..count).into_par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let indices = 0..count;
+
+        let forces: Vec<Vec2> = indices
+            .map(|i| {
+                let p = nodes[i];
+                let previous = nodes[(i + count - 1) % count];
+                let next = nodes[(i + 1) % count];
+
+                let mut force = ((previous + next) / 2.0 - p) * self.settings.attraction;
+
+                for j in hash.nearby(p) {
+                    let offset = p - nodes[j];
+                    let distance = offset.length();
+
+                    if j != i && distance > 0.0 && distance < radius {
+                        force +=
+                            offset / distance * (1.0 - distance / radius) * self.settings.repulsion;
+                    }
+                }
+
+                force
+            })
+            .collect();
+
+        for (node, force) in self.nodes.iter_mut().zip(forces) {
+            *node += force * self.settings.step_length;
+        }
+
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let rng = &mut self.rng;
+
+        let mut grown = Vec::with_capacity(count * 2);
+
+        for i in 0..count {
+            let a = self.nodes[i];
+            let b = self.nodes[(i + 1) % count];
+
+            grown.push(a);
+
+            let split = a.distance(b) > self.settings.max_edge_length
+                || rng.gen::<f32>() < self.settings.growth_rate;
+
+            if split && count + (grown.len() - i - 1) < self.settings.max_nodes {
+                grown.push((a + b) / 2.0);
+            }
+        }
+
+        self.nodes = grown;
+    }
+
+    /// Renders the snapshots and the final curve.
+    fn render(&mut self) {
+        self.canvas = VectorCanvas::default();
+
+        let last = self.snapshots.len().max(1) as f32;
+
+        for (i, curve) in self.snapshots.iter().chain(Some(&self.nodes)).enumerate() {
+            if curve.is_empty() {
+                continue;
+            }
+
+            let mut path = curve.clone();
+            path.push(curve[0]);
+
+            let stroke = map_stroke(
+                self.settings.stroke,
+                self.settings.stroke_colors.as_ref(),
+                i as f32 / last,
+            );
+
+            self.canvas.draw_shape(path, Some(stroke), None);
+        }
+    }
+
+    /// Simulates and renders the curve.
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        for i in 0..self.settings.steps {
+            before_iter(i as f32 / self.settings.steps as f32);
+
+            if let Some(interval) = self.settings.snapshot_interval {
+                if i > 0 && i % interval.max(1) == 0 {
+                    self.snapshots.push(self.nodes.clone());
+                }
+            }
+
+            self.step();
+        }
+
+        self.render();
+    }
+}
+
+impl<P> Sketcher<P> for GrowthSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
@@ -2,22 +2,35 @@ use std::f32::consts::PI;
 
 use barium::{Color, Mat2, UVec2, Vec2};
 
-use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::canvas::{LayeredCanvas, OmniCanvas, RasterCanvas};
+use crate::palette::ColorMap;
 
 use super::Sketcher;
 
+/// Which inks a [HalftoneSketcher] separates the image into.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HalftoneSeparation {
+    /// A single screen of dots, sized by the darkness of the image and colored by the dot color.
+    Value,
+    /// Screens of cyan, magenta, yellow and black dots, each at its own angle and in its own layer (named after the ink),
+    /// as used in printing. The dot color is not used.
+    Cmyk,
+}
+
 #[derive(Clone)]
 pub struct HalftoneSketcherSettings {
     pub dot_density: f32,
     pub dot_scale: f32,
     pub dot_sides: usize,
-    pub dot_color: Color,
+    /// The color of the dots, mapped from the darkness of the image under each dot (`0.0` to `1.0`).
+    pub dot_color: ColorMap,
+    pub separation: HalftoneSeparation,
 }
 
 pub struct HalftoneSketcher {
     settings: HalftoneSketcherSettings,
     input_image: RasterCanvas,
-    canvas: VectorCanvas,
+    canvas: LayeredCanvas,
 }
 
 impl HalftoneSketcher {
@@ -25,11 +38,55 @@ impl HalftoneSketcher {
         Self {
             settings,
             input_image,
-            canvas: VectorCanvas::default(),
+            canvas: LayeredCanvas::new(),
         }
     }
 
+    /// The name, screen angle (in degrees) and color of each ink of [HalftoneSeparation::Cmyk], in the order they are printed.
+    const CMYK_SCREENS: [(&'static str, f32, [f32; 3]); 4] = [
+        ("cyan", 15.0, [0.0, 1.0, 1.0]),
+        ("magenta", 75.0, [1.0, 0.0, 1.0]),
+        ("yellow", 0.0, [1.0, 1.0, 0.0]),
+        ("black", 45.0, [0.0, 0.0, 0.0]),
+    ];
+
     fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        match self.settings.separation {
+            HalftoneSeparation::Value => {
+                let dot_color = self.settings.dot_color.clone();
+
+                self.draw_screen(
+                    "dots",
+                    PI / 4.0,
+                    |pixel| 1.0 - pixel.value(),
+                    |darkness| dot_color.sample(darkness),
+                    &before_iter,
+                );
+            }
+            HalftoneSeparation::Cmyk => {
+                for (i, (name, angle, [r, g, b])) in Self::CMYK_SCREENS.into_iter().enumerate() {
+                    self.draw_screen(
+                        name,
+                        angle.to_radians(),
+                        |pixel| cmyk(pixel)[i],
+                        |_| Color::new(r, g, b, 1.0),
+                        &|progress| before_iter((i as f32 + progress) / 4.0),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Draws a grid of dots, turned by an angle, into a layer.
+    /// Each dot is sized by the amount of ink the image calls for under it, from `0.0` to `1.0`.
+    fn draw_screen<A: Fn(Color) -> f32, C: Fn(f32) -> Color, P: Fn(f32)>(
+        &mut self,
+        layer: &str,
+        angle: f32,
+        amount: A,
+        color: C,
+        before_iter: &P,
+    ) {
         let half_size = Vec2::new(
             self.input_image.width() as f32,
             self.input_image.height() as f32,
@@ -43,7 +100,7 @@ impl HalftoneSketcher {
             Vec2::Y * (-2.0 / major_dimension),
         );
 
-        let rotate_mat = Mat2::from_angle(PI / 4.0);
+        let rotate_mat = Mat2::from_angle(angle);
 
         let dot_dim = Vec2::new(
             (self.input_image.width() as f32 / self.settings.dot_density),
@@ -65,21 +122,22 @@ impl HalftoneSketcher {
                 dot_pos = rotate_mat.mul_vec2(dot_pos);
                 dot_pos += half_size;
 
-                let dot_size = self.settings.dot_density / major_dimension
-                    * (1.0
-                        - self
-                            .input_image
-                            .get_pixel(dot_pos.x as usize, dot_pos.y as usize)
-                            .value())
-                    / 2.0_f32.sqrt() * self.settings.dot_scale;
+                let darkness = amount(
+                    self.input_image
+                        .get_pixel(dot_pos.x as usize, dot_pos.y as usize),
+                );
+
+                let dot_size = self.settings.dot_density / major_dimension * darkness
+                    / 2.0_f32.sqrt()
+                    * self.settings.dot_scale;
 
-                self.canvas.draw_regular_polygon(
+                self.canvas.layer_mut(layer).draw_regular_polygon(
                     scale_mat.mul_vec2(dot_pos - half_size),
                     self.settings.dot_sides,
                     dot_size,
                     0.0,
                     None,
-                    Some(self.settings.dot_color),
+                    Some(color(darkness)),
                 );
 
                 i.y += 1.0;
@@ -88,6 +146,27 @@ impl HalftoneSketcher {
             i.x += 1.0;
         }
     }
+
+    /// The rendered canvas, which only has layers if the image was separated into inks.
+    fn output(&self, canvas: LayeredCanvas) -> OmniCanvas {
+        match self.settings.separation {
+            HalftoneSeparation::Value => canvas.into_flattened().into(),
+            HalftoneSeparation::Cmyk => canvas.into(),
+        }
+    }
+}
+
+/// Separates a color into the amounts of cyan, magenta, yellow and black ink that print it.
+fn cmyk(color: Color) -> [f32; 4] {
+    let black = 1.0 - color.r().max(color.g()).max(color.b());
+
+    if black >= 1.0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+
+    let ink = |channel: f32| (1.0 - channel - black) / (1.0 - black);
+
+    [ink(color.r()), ink(color.g()), ink(color.b()), black]
 }
 
 impl<F> Sketcher<F> for HalftoneSketcher
@@ -97,12 +176,13 @@ where
     fn run(&mut self, before_iter: F) -> OmniCanvas {
         self.run(before_iter);
 
-        self.canvas.clone().into()
+        self.output(self.canvas.clone())
     }
 
     fn run_and_dispose(mut self, before_iter: F) -> OmniCanvas {
         self.run(before_iter);
 
-        self.canvas.into()
+        let canvas = std::mem::take(&mut self.canvas);
+        self.output(canvas)
     }
 }
@@ -0,0 +1,202 @@
+use barium::{Color, Stroke, Vec2};
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::helpers::{cumulative, edge_importance, sample_cumulative, PixelTransform};
+use crate::palette::{map_stroke, ColorMap};
+use crate::triangulation::{delaunay, polygon_centroid, voronoi_cells};
+
+/// Which polygons a [LowPolySketcher] fills.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LowPolyMode {
+    /// The triangles of the Delaunay triangulation of the vertices.
+    Triangles,
+    /// The Voronoi cells around each vertex, for a mosaic.
+    Voronoi,
+}
+
+/// How a [LowPolySketcher] picks the color of each polygon.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LowPolyFill {
+    /// The average color of the input image under the polygon.
+    Average,
+    /// The color of the input image at the centroid of the polygon.
+    Centroid,
+}
+
+#[derive(Clone)]
+pub struct LowPolySketcherSettings {
+    /// The number of vertices to pick from the input image, not including those on the border.
+    pub vertices: usize,
+    /// How much vertices are drawn towards edges in the input image.
+    /// At `0.0` they are placed uniformly, and at `1.0` they are placed in proportion to edge strength.
+    pub edge_bias: f32,
+    /// Radius of a box blur applied to the input image before finding edges. For no box blur, set this to zero.
+    pub edge_blur_radius: usize,
+    /// The number of vertices spread evenly along each side of the border, in addition to the corners.
+    pub border_vertices: usize,
+    /// Which polygons to fill.
+    pub mode: LowPolyMode,
+    /// How to color each polygon.
+    pub fill: LowPolyFill,
+    /// If set, the outline of each polygon is drawn with this stroke.
+    pub wireframe: Option<Stroke>,
+    /// If set, colors the outline of each polygon by the brightness of its fill, instead of the wireframe color.
+    pub wireframe_colors: Option<ColorMap>,
+}
+
+/// Renders an image as flat-colored triangles (or Voronoi cells) between vertices picked from the image.
+pub struct LowPolySketcher {
+    settings: LowPolySketcherSettings,
+    transform: PixelTransform,
+    input_image: RasterCanvas,
+    canvas: VectorCanvas,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+impl LowPolySketcher {
+    pub fn new(
+        input_image: RasterCanvas,
+        settings: LowPolySketcherSettings,
+        #[cfg(feature = "small-rng")] seed: u64,
+    ) -> Self {
+        Self {
+            settings,
+            transform: PixelTransform::new(input_image.width(), input_image.height()),
+            input_image,
+            canvas: VectorCanvas::default(),
+            #[cfg(feature = "small-rng")]
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Consumes the sketcher and returns the input image.
+    pub fn take_input(self) -> RasterCanvas {
+        self.input_image
+    }
+
+    /// Picks the vertices, in pixel space.
+    fn pick_vertices(&mut self) -> Vec<Vec2> {
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let mut rng = &mut self.rng;
+
+        let width = self.input_image.width();
+        let height = self.input_image.height();
+        let size = Vec2::new(width as f32, height as f32);
+
+        let mut vertices =
+            Vec::with_capacity(self.settings.vertices + 4 * (self.settings.border_vertices + 1));
+
+        // The corners and border keep the polygons covering the entire image.
+        let border = self.settings.border_vertices + 1;
+        for i in 0..border {
+            let t = i as f32 / border as f32;
+
+            vertices.push(Vec2::new(t * size.x, 0.0));
+            vertices.push(Vec2::new(size.x, t * size.y));
+            vertices.push(Vec2::new((1.0 - t) * size.x, size.y));
+            vertices.push(Vec2::new(0.0, (1.0 - t) * size.y));
+        }
+
+        let importance = cumulative(edge_importance(
+            &self.input_image,
+            self.settings.edge_blur_radius,
+            self.settings.edge_bias,
+        ));
+
+        for _ in 0..self.settings.vertices {
+            let i = sample_cumulative(&importance, &mut rng);
+
+            vertices.push(Vec2::new(
+                (i % width) as f32 + rng.gen::<f32>(),
+                (i / width) as f32 + rng.gen::<f32>(),
+            ));
+        }
+
+        vertices
+    }
+
+    /// Picks the color of a polygon (in pixel space).
+    fn polygon_color(&self, polygon: &[Vec2]) -> Color {
+        let centroid_color = || {
+            let centroid = polygon_centroid(polygon).unwrap_or(polygon[0]);
+
+            self.input_image
+                .get_pixel(centroid.x.max(0.0) as usize, centroid.y.max(0.0) as usize)
+        };
+
+        match self.settings.fill {
+            LowPolyFill::Centroid => centroid_color(),
+            // Slivers may not cover the center of any pixel.
+            LowPolyFill::Average => self
+                .input_image
+                .get_average_color(polygon)
+                .unwrap_or_else(centroid_color),
+        }
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        // There is nothing to sample vertices or colors from in an empty image.
+        if self.input_image.width() == 0 || self.input_image.height() == 0 {
+            return;
+        }
+
+        let vertices = self.pick_vertices();
+
+        let polygons: Vec<Vec<Vec2>> = match self.settings.mode {
+            LowPolyMode::Triangles => delaunay(&vertices)
+                .into_iter()
+                .map(|corners| corners.iter().map(|&i| vertices[i]).collect())
+                .collect(),
+            LowPolyMode::Voronoi => voronoi_cells(
+                &vertices,
+                Vec2::ZERO,
+                Vec2::new(
+                    self.input_image.width() as f32,
+                    self.input_image.height() as f32,
+                ),
+            )
+            .into_iter()
+            .filter(|cell| cell.len() >= 3)
+            .collect(),
+        };
+
+        for (i, polygon) in polygons.iter().enumerate() {
+            before_iter(i as f32 / polygons.len() as f32);
+
+            let color = self.polygon_color(polygon);
+            let brightness = (color.r() + color.g() + color.b()) / 3.0;
+
+            self.canvas.draw_shape(
+                polygon.iter().map(|p| self.transform.apply(*p)).collect(),
+                self.settings.wireframe.map(|stroke| {
+                    map_stroke(stroke, self.settings.wireframe_colors.as_ref(), brightness)
+                }),
+                Some(color),
+            );
+        }
+    }
+}
+
+impl<P> Sketcher<P> for LowPolySketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fmt;
+use std::sync::Arc;
+
+use barium::{Color, LineEnd, Stroke, Vec2};
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, VectorCanvas};
+use crate::palette::{map_stroke, ColorMap};
+
+/// A symbol of an L-system, with optional parameters.
+///
+/// The turtle interprets these symbols, and ignores any others:
+/// * `F` and `G` move forward while drawing. A parameter scales the step.
+/// * `f` moves forward without drawing. A parameter scales the step.
+/// * `+` and `-` turn left and right by the angle. A parameter replaces the angle (in radians).
+/// * `|` turns around.
+/// * `[` and `]` push and pop the position, heading and width of the turtle.
+/// * `!` multiplies the width by the width decay. A parameter replaces the width, relative to the initial width.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LSystemModule {
+    pub symbol: char,
+    pub parameters: Vec<f32>,
+}
+
+impl LSystemModule {
+    pub fn new(symbol: char, parameters: Vec<f32>) -> Self {
+        Self { symbol, parameters }
+    }
+
+    /// Parses a string of modules, such as `"F(0.5)[+X]"`. Parameters are given in parentheses, separated by commas,
+    /// and empty parentheses are the same as none. Whitespace is ignored.
+    pub fn parse(text: &str) -> Result<Vec<Self>, LSystemParseError> {
+        let mut modules = Vec::new();
+        let mut chars = text.chars().filter(|c| !c.is_whitespace()).peekable();
+
+        while let Some(symbol) = chars.next() {
+            let mut parameters = Vec::new();
+
+            if chars.peek() == Some(&'(') {
+                chars.next();
+
+                let mut list = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some(')') => break,
+                        Some(c) => list.push(c),
+                        None => return Err(LSystemParseError::UnclosedParameters(symbol)),
+                    }
+                }
+
+                if !list.is_empty() {
+                    parameters = list
+                        .split(',')
+                        .map(|parameter| {
+                            parameter.parse().map_err(|_| {
+                                LSystemParseError::InvalidParameter(parameter.to_string())
+                            })
+                        })
+                        .collect::<Result<_, _>>()?;
+                }
+            }
+
+            modules.push(Self { symbol, parameters });
+        }
+
+        Ok(modules)
+    }
+}
+
+/// Why a string of [LSystemModule]s couldn't be parsed.
+#[derive(Clone, PartialEq, Debug)]
+pub enum LSystemParseError {
+    /// A parameter is not a number.
+    InvalidParameter(String),
+    /// The parameters of a module are missing their closing parenthesis.
+    UnclosedParameters(char),
+}
+
+impl fmt::Display for LSystemParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidParameter(parameter) => {
+                write!(f, "invalid L-system parameter `{}`", parameter)
+            }
+            Self::UnclosedParameters(symbol) => {
+                write!(f, "unclosed parameters of L-system module `{}`", symbol)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LSystemParseError {}
+
+type ParametricProduction = dyn Fn(&[f32]) -> Option<Vec<LSystemModule>> + Send + Sync;
+
+/// How an [LSystemRule] replaces a module.
+#[derive(Clone)]
+pub enum LSystemProduction {
+    /// A fixed string of modules.
+    Fixed(Vec<LSystemModule>),
+    /// Computed from the parameters of the replaced module.
+    /// Returning `None` means the rule doesn't apply, which can be used for conditions.
+    Parametric(Arc<ParametricProduction>),
+}
+
+/// A production rule of an L-system.
+///
+/// If several rules apply to the same module, one of them is picked at random, in proportion to their weights.
+/// Modules with no applicable rule are kept as they are.
+#[derive(Clone)]
+pub struct LSystemRule {
+    /// The symbol this rule replaces.
+    pub predecessor: char,
+    /// The relative chance of picking this rule over others for the same symbol.
+    pub weight: f32,
+    /// What the symbol is replaced with.
+    pub production: LSystemProduction,
+}
+
+impl LSystemRule {
+    /// Creates a rule that always replaces a symbol with a string of modules (see [LSystemModule::parse]).
+    pub fn new(predecessor: char, successor: &str) -> Result<Self, LSystemParseError> {
+        Self::stochastic(predecessor, 1.0, successor)
+    }
+
+    /// Creates a rule that replaces a symbol with a string of modules, with a weight for picking between rules.
+    pub fn stochastic(
+        predecessor: char,
+        weight: f32,
+        successor: &str,
+    ) -> Result<Self, LSystemParseError> {
+        Ok(Self {
+            predecessor,
+            weight,
+            production: LSystemProduction::Fixed(LSystemModule::parse(successor)?),
+        })
+    }
+
+    /// Creates a rule that computes the replacement from the parameters of a module.
+    pub fn parametric<F>(predecessor: char, production: F) -> Self
+    where
+        F: Fn(&[f32]) -> Option<Vec<LSystemModule>> + Send + Sync + 'static,
+    {
+        Self {
+            predecessor,
+            weight: 1.0,
+            production: LSystemProduction::Parametric(Arc::new(production)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LSystemSketcherSettings {
+    /// The initial string of modules.
+    pub axiom: Vec<LSystemModule>,
+    /// The production rules.
+    pub rules: Vec<LSystemRule>,
+    /// The number of times to apply the rules.
+    pub iterations: usize,
+    /// The angle the turtle turns by, in radians.
+    pub angle: f32,
+    /// The initial heading of the turtle, in radians counter-clockwise from the right.
+    pub heading: f32,
+    /// The distance the turtle moves with each step, in canvas space. The turtle starts at the center of the canvas.
+    /// Only used when `fit` is off, since fitting scales the whole drawing to the canvas.
+    pub step: f32,
+    /// Whether to scale and move the drawing to fill the canvas, leaving a margin.
+    pub fit: bool,
+    /// The stroke to draw with. Its width is in the final canvas space, after fitting.
+    pub stroke: Stroke,
+    /// If set, colors each path by the order it was drawn in, from the first (`0.0`) to the last (`1.0`),
+    /// instead of the stroke color. A path ends wherever the turtle branches, jumps or changes width.
+    pub stroke_colors: Option<ColorMap>,
+    /// How much the width of the stroke is multiplied by with each `!`.
+    pub width_decay: f32,
+    /// Space left around the fitted drawing, as a fraction of the canvas. Only used when `fit` is on.
+    pub margin: f32,
+}
+
+impl LSystemSketcherSettings {
+    /// Creates the settings of a preset, from fixed rules given as a predecessor, a weight and a successor.
+    fn preset(axiom: &str, rules: &[(char, f32, &str)], iterations: usize, angle: f32) -> Self {
+        // The strings of the presets are known to parse.
+        Self {
+            axiom: LSystemModule::parse(axiom).unwrap(),
+            rules: rules
+                .iter()
+                .map(|&(predecessor, weight, successor)| {
+                    LSystemRule::stochastic(predecessor, weight, successor).unwrap()
+                })
+                .collect(),
+            iterations,
+            angle: angle.to_radians(),
+            heading: 0.0,
+            step: 0.01,
+            fit: true,
+            stroke: Stroke {
+                color: Color::black(),
+                width: 0.005,
+                line_end: LineEnd::Round,
+            },
+            stroke_colors: None,
+            width_decay: 0.7,
+            margin: 0.05,
+        }
+    }
+
+    /// The Koch snowflake.
+    pub fn koch(iterations: usize) -> Self {
+        Self::preset("F--F--F", &[('F', 1.0, "F+F--F+F")], iterations, 60.0)
+    }
+
+    /// The Hilbert curve.
+    pub fn hilbert(iterations: usize) -> Self {
+        Self::preset(
+            "A",
+            &[('A', 1.0, "+BF-AFA-FB+"), ('B', 1.0, "-AF+BFB+FA-")],
+            iterations,
+            90.0,
+        )
+    }
+
+    /// The Heighway dragon curve.
+    pub fn dragon(iterations: usize) -> Self {
+        Self::preset(
+            "F",
+            &[('F', 1.0, "F+G"), ('G', 1.0, "F-G")],
+            iterations,
+            90.0,
+        )
+    }
+
+    /// A branching plant, growing upwards.
+    pub fn plant(iterations: usize) -> Self {
+        Self {
+            heading: PI / 2.0,
+            ..Self::preset(
+                "X",
+                &[('X', 1.0, "F+[[!X]-!X]-F[-!FX]+!X"), ('F', 1.0, "FF")],
+                iterations,
+                25.0,
+            )
+        }
+    }
+
+    /// A weed-like plant that grows differently every time, using stochastic rules.
+    pub fn stochastic_plant(iterations: usize) -> Self {
+        Self {
+            heading: PI / 2.0,
+            ..Self::preset(
+                "F",
+                &[
+                    ('F', 0.33, "F[+!F]F[-!F]F"),
+                    ('F', 0.33, "F[+!F]F"),
+                    ('F', 0.34, "F[-!F]F"),
+                ],
+                iterations,
+                25.7,
+            )
+        }
+    }
+
+    /// A tree whose branches shrink until they are too short to split, using parametric rules.
+    pub fn tree(iterations: usize) -> Self {
+        Self {
+            heading: PI / 2.0,
+            rules: vec![LSystemRule::parametric('A', |parameters| {
+                let length = parameters.first().copied().unwrap_or(1.0);
+
+                (length > 0.1).then(|| {
+                    vec![
+                        LSystemModule::new('F', vec![length]),
+                        LSystemModule::new('[', vec![]),
+                        LSystemModule::new('+', vec![]),
+                        LSystemModule::new('!', vec![]),
+                        LSystemModule::new('A', vec![length * 0.75]),
+                        LSystemModule::new(']', vec![]),
+                        LSystemModule::new('[', vec![]),
+                        LSystemModule::new('-', vec![]),
+                        LSystemModule::new('!', vec![]),
+                        LSystemModule::new('A', vec![length * 0.65]),
+                        LSystemModule::new(']', vec![]),
+                    ]
+                })
+            })],
+            ..Self::preset("A(1)", &[], iterations, 30.0)
+        }
+    }
+}
+
+/// Grows a string of symbols with an L-system, then draws it with turtle graphics.
+pub struct LSystemSketcher {
+    settings: LSystemSketcherSettings,
+    canvas: VectorCanvas,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+impl LSystemSketcher {
+    pub fn new(settings: LSystemSketcherSettings, #[cfg(feature = "small-rng")] seed: u64) -> Self {
+        Self {
+            settings,
+            canvas: VectorCanvas::default(),
+            #[cfg(feature = "small-rng")]
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Applies the rules to every module once.
+    fn rewrite(
+        &mut self,
+        modules: Vec<LSystemModule>,
+        rules: &HashMap<char, Vec<LSystemRule>>,
+    ) -> Vec<LSystemModule> {
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let rng = &mut self.rng;
+
+        let mut rewritten = Vec::with_capacity(modules.len());
+        let mut candidates = Vec::new();
+
+        for module in modules {
+            candidates.clear();
+
+            for rule in rules.get(&module.symbol).into_iter().flatten() {
+                let successor = match &rule.production {
+                    LSystemProduction::Fixed(successor) => Some(successor.clone()),
+                    LSystemProduction::Parametric(production) => production(&module.parameters),
+                };
+
+                if let Some(successor) = successor {
+                    candidates.push((rule.weight, successor));
+                }
+            }
+
+            let total: f32 = candidates.iter().map(|(weight, _)| weight).sum();
+
+            if candidates.is_empty() {
+                rewritten.push(module);
+            } else {
+                let mut choice = rng.gen::<f32>() * total;
+
+                let index = candidates
+                    .iter()
+                    .position(|(weight, _)| {
+                        choice -= weight;
+                        choice < 0.0
+                    })
+                    .unwrap_or(candidates.len() - 1);
+
+                rewritten.append(&mut candidates[index].1);
+            }
+        }
+
+        rewritten
+    }
+
+    /// Interprets the modules with a turtle, returning each drawn path with its relative width.
+    fn trace(&self, modules: &[LSystemModule]) -> Vec<(Vec<Vec2>, f32)> {
+        let mut paths = Vec::new();
+        let mut path = Vec::new();
+
+        let mut position = Vec2::ZERO;
+        let mut heading = self.settings.heading;
+        let mut width = 1.0;
+        let mut stack = Vec::new();
+
+        for module in modules {
+            let parameter = module.parameters.first().copied();
+            let path_width = width;
+
+            // Every change to the pen other than drawing ends the current path.
+            let mut end_path = true;
+
+            match module.symbol {
+                'F' | 'G' => {
+                    if path.is_empty() {
+                        path.push(position);
+                    }
+
+                    position += Vec2::new(heading.cos(), heading.sin())
+                        * (parameter.unwrap_or(1.0) * self.settings.step);
+                    path.push(position);
+
+                    end_path = false;
+                }
+                'f' => {
+                    position += Vec2::new(heading.cos(), heading.sin())
+                        * (parameter.unwrap_or(1.0) * self.settings.step);
+                }
+                '+' => {
+                    heading += parameter.unwrap_or(self.settings.angle);
+                    end_path = false;
+                }
+                '-' => {
+                    heading -= parameter.unwrap_or(self.settings.angle);
+                    end_path = false;
+                }
+                '|' => {
+                    heading += PI;
+                    end_path = false;
+                }
+                '[' => stack.push((position, heading, width)),
+                ']' => {
+                    if let Some((last_position, last_heading, last_width)) = stack.pop() {
+                        position = last_position;
+                        heading = last_heading;
+                        width = last_width;
+                    }
+                }
+                '!' => width = parameter.unwrap_or(width * self.settings.width_decay),
+                _ => end_path = false,
+            }
+
+            if end_path && !path.is_empty() {
+                paths.push((std::mem::take(&mut path), path_width));
+            }
+        }
+
+        if !path.is_empty() {
+            paths.push((path, width));
+        }
+
+        paths
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        let mut rules: HashMap<char, Vec<LSystemRule>> = HashMap::new();
+        for rule in &self.settings.rules {
+            rules
+                .entry(rule.predecessor)
+                .or_default()
+                .push(rule.clone());
+        }
+
+        let mut modules = self.settings.axiom.clone();
+
+        for i in 0..self.settings.iterations {
+            before_iter(i as f32 / self.settings.iterations as f32);
+
+            modules = self.rewrite(modules, &rules);
+        }
+
+        let paths = self.trace(&modules);
+
+        // Fit the drawing into -1..1, keeping its aspect ratio.
+        let (center, scale) = if self.settings.fit {
+            let (min, max) = paths.iter().flat_map(|(path, _)| path).fold(
+                (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+                |(min, max), &p| (min.min(p), max.max(p)),
+            );
+
+            (
+                (min + max) / 2.0,
+                2.0 * (1.0 - self.settings.margin) / (max - min).max_element().max(f32::EPSILON),
+            )
+        } else {
+            (Vec2::ZERO, 1.0)
+        };
+
+        let last = paths.len().saturating_sub(1).max(1) as f32;
+
+        for (i, (path, width)) in paths.into_iter().enumerate() {
+            let stroke = map_stroke(
+                self.settings.stroke,
+                self.settings.stroke_colors.as_ref(),
+                i as f32 / last,
+            );
+
+            self.canvas.draw_shape(
+                path.into_iter().map(|p| (p - center) * scale).collect(),
+                Some(Stroke {
+                    width: stroke.width * width,
+                    ..stroke
+                }),
+                None,
+            );
+        }
+    }
+}
+
+impl<P> Sketcher<P> for LSystemSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
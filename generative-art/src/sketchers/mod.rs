@@ -1,14 +1,58 @@
+mod attractor;
 mod celestial;
 mod fragment;
 mod wave;
 mod preslav;
+mod particle;
 mod halftone;
+mod spiral;
+mod primitive;
+mod reaction;
+mod lowpoly;
+mod mosaic;
+mod glyph;
+mod growth;
+mod lsystem;
+mod subdivision;
+mod tile;
+mod flame;
+mod fractal;
+mod passes;
 
+pub use attractor::{
+    AttractorMap, AttractorProjection, AttractorSketcher, AttractorSketcherSettings,
+};
 pub use celestial::{CelestialSketcher, CelestialSketcherSettings};
 pub use fragment::{RasterFragmentSketcher, VectorFragmentSketcher};
-pub use wave::{WaveSketcher, WaveSketcherSettings};
-pub use preslav::{PreslavSketcher, PreslavSketcherSettings};
-pub use halftone::{HalftoneSketcher, HalftoneSketcherSettings};
+pub use glyph::{Glyph, GlyphMatching, GlyphSketcher, GlyphSketcherSettings, DEFAULT_RAMP};
+pub use tile::{Tile, TileSelection, TileSketcher, TileSketcherSettings};
+pub use wave::{WavePhase, WaveSketcher, WaveSketcherSettings, Waveform};
+pub use particle::{
+    Attractor, Drag, Force, ImageGradient, NoiseField, Particle, ParticleSketcher,
+    ParticleSketcherSettings, Vortex,
+};
+pub use preslav::{PreslavPlacement, PreslavShape, PreslavSketcher, PreslavSketcherSettings};
+pub use growth::{GrowthSketcher, GrowthSketcherSettings};
+pub use halftone::{HalftoneSeparation, HalftoneSketcher, HalftoneSketcherSettings};
+pub use lsystem::{
+    LSystemModule, LSystemParseError, LSystemProduction, LSystemRule, LSystemSketcher,
+    LSystemSketcherSettings,
+};
+pub use lowpoly::{LowPolyFill, LowPolyMode, LowPolySketcher, LowPolySketcherSettings};
+pub use mosaic::{MosaicSketcher, MosaicSketcherSettings};
+pub use primitive::{PrimitiveShape, PrimitiveSketcher, PrimitiveSketcherSettings};
+pub use subdivision::{
+    SubdivisionFill, SubdivisionShape, SubdivisionSketcher, SubdivisionSketcherSettings,
+};
+pub use reaction::{
+    ReactionDiffusionSketcher, ReactionDiffusionSketcherSettings, ReactionOutput, ReactionSeed,
+};
+pub use flame::{FlameGenome, FlameSketcher, FlameSketcherSettings, FlameTransform, Variation};
+pub use fractal::{
+    FractalColoring, FractalKind, FractalSketcher, FractalSketcherSettings, OrbitTrap,
+};
+pub use passes::{HatchStyle, VectorPass, VectorPassSketcher};
+pub use spiral::{SpiralMode, SpiralModulation, SpiralSketcher, SpiralSketcherSettings};
 
 use crate::canvas::OmniCanvas;
 
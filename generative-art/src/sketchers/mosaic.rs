@@ -0,0 +1,184 @@
+use barium::{Color, Stroke, Vec2};
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::helpers::{
+    cumulative, edge_importance, polygon_spans, sample_cumulative, PixelTransform,
+};
+use crate::palette::{map_stroke, ColorMap};
+use crate::triangulation::{polygon_centroid, voronoi_cells};
+
+#[derive(Clone)]
+pub struct MosaicSketcherSettings {
+    /// The number of cells to split the image into.
+    pub cells: usize,
+    /// How much cells are drawn towards edges in the input image, making them smaller there.
+    /// At `0.0` they are placed uniformly, and at `1.0` they are placed in proportion to edge strength.
+    pub edge_bias: f32,
+    /// Radius of a box blur applied to the input image before finding edges. For no box blur, set this to zero.
+    pub edge_blur_radius: usize,
+    /// The number of iterations of Lloyd relaxation, which evens out the shapes of the cells.
+    /// Relaxation is weighted by the same edge strength the cells are placed with.
+    pub relaxation_iterations: usize,
+    /// If set, the borders between cells are drawn with this stroke, like the lead of stained glass.
+    pub lead: Option<Stroke>,
+    /// If set, colors the lead around each cell by the brightness of the cell, instead of the lead color.
+    pub lead_colors: Option<ColorMap>,
+}
+
+/// Tessellates an image into Voronoi cells, each filled with the average color underneath.
+pub struct MosaicSketcher {
+    settings: MosaicSketcherSettings,
+    transform: PixelTransform,
+    input_image: RasterCanvas,
+    canvas: VectorCanvas,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+impl MosaicSketcher {
+    pub fn new(
+        input_image: RasterCanvas,
+        settings: MosaicSketcherSettings,
+        #[cfg(feature = "small-rng")] seed: u64,
+    ) -> Self {
+        Self {
+            settings,
+            transform: PixelTransform::new(input_image.width(), input_image.height()),
+            input_image,
+            canvas: VectorCanvas::default(),
+            #[cfg(feature = "small-rng")]
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Consumes the sketcher and returns the input image.
+    pub fn take_input(self) -> RasterCanvas {
+        self.input_image
+    }
+
+    /// Picks the initial seed points, in pixel space.
+    fn pick_seeds(&mut self, importance: &[f32]) -> Vec<Vec2> {
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let mut rng = &mut self.rng;
+
+        let width = self.input_image.width();
+        let importance = cumulative(importance.iter().copied());
+
+        (0..self.settings.cells)
+            .map(|_| {
+                let i = sample_cumulative(&importance, &mut rng);
+
+                Vec2::new(
+                    (i % width) as f32 + rng.gen::<f32>(),
+                    (i / width) as f32 + rng.gen::<f32>(),
+                )
+            })
+            .collect()
+    }
+
+    /// Moves each seed to the weighted centroid of its cell.
+    fn relax(&self, seeds: &mut [Vec2], cells: &[Vec<Vec2>], importance: &[f32]) {
+        let width = self.input_image.width();
+
+        for (seed, cell) in seeds.iter_mut().zip(cells) {
+            let mut weighted = Vec2::ZERO;
+            let mut total = 0.0;
+
+            for (y, columns) in polygon_spans(cell, width, self.input_image.height()) {
+                for x in columns {
+                    let weight = importance[y * width + x];
+
+                    weighted += Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * weight;
+                    total += weight;
+                }
+            }
+
+            if total > 0.0 {
+                *seed = weighted / total;
+            } else if let Some(centroid) = polygon_centroid(cell) {
+                *seed = centroid;
+            }
+        }
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        let size = Vec2::new(
+            self.input_image.width() as f32,
+            self.input_image.height() as f32,
+        );
+
+        let importance = edge_importance(
+            &self.input_image,
+            self.settings.edge_blur_radius,
+            self.settings.edge_bias,
+        );
+
+        let mut seeds = self.pick_seeds(&importance);
+        let mut cells = voronoi_cells(&seeds, Vec2::ZERO, size);
+
+        // Relaxation takes most of the time, so it is what progress is reported for.
+        for i in 0..self.settings.relaxation_iterations {
+            before_iter(i as f32 / self.settings.relaxation_iterations as f32);
+
+            self.relax(&mut seeds, &cells, &importance);
+            cells = voronoi_cells(&seeds, Vec2::ZERO, size);
+        }
+
+        let cells: Vec<(Vec<Vec2>, f32)> = cells
+            .into_iter()
+            .filter(|cell| cell.len() >= 3)
+            .map(|cell| {
+                let color = self
+                    .input_image
+                    .get_average_color(&cell)
+                    .unwrap_or_else(Color::transparent);
+
+                let cell: Vec<Vec2> = cell.into_iter().map(|p| self.transform.apply(p)).collect();
+                self.canvas.draw_shape(cell.clone(), None, Some(color));
+
+                (cell, (color.r() + color.g() + color.b()) / 3.0)
+            })
+            .collect();
+
+        // Lead is drawn on top of every cell, so that neighbouring cells don't cover it.
+        if let Some(lead) = self.settings.lead {
+            for (cell, brightness) in cells {
+                let mut outline = cell;
+                outline.push(outline[0]);
+
+                self.canvas.draw_shape(
+                    outline,
+                    Some(map_stroke(
+                        lead,
+                        self.settings.lead_colors.as_ref(),
+                        brightness,
+                    )),
+                    None,
+                );
+            }
+        }
+    }
+}
+
+impl<P> Sketcher<P> for MosaicSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
@@ -0,0 +1,298 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use barium::{Stroke, Vec2};
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::helpers::{value_noise, PixelTransform};
+use crate::palette::{map_stroke, ColorMap};
+
+/// The state of a particle in a [ParticleSketcher].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Particle {
+    pub position: Vec2,
+    pub velocity: Vec2,
+}
+
+/// A force that moves the particles of a [ParticleSketcher].
+///
+/// Particles have no mass, so forces are applied directly as accelerations.
+pub trait Force: Send + Sync {
+    /// Computes the force on a particle, given the time since the simulation started.
+    fn force(&self, particle: &Particle, time: f32) -> Vec2;
+}
+
+/// Pulls particles towards a point, weaker with distance. A negative strength pushes them away instead.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Attractor {
+    pub position: Vec2,
+    pub strength: f32,
+    /// Keeps the force from growing without limit near the point.
+    pub softening: f32,
+}
+
+impl Force for Attractor {
+    fn force(&self, particle: &Particle, _time: f32) -> Vec2 {
+        let offset = self.position - particle.position;
+
+        offset * self.strength
+            / (offset.length_squared() + self.softening * self.softening).powf(1.5)
+    }
+}
+
+/// Swirls particles around a point, counter-clockwise for a positive strength.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Vortex {
+    pub position: Vec2,
+    pub strength: f32,
+    /// Keeps the force from growing without limit near the point.
+    pub softening: f32,
+}
+
+impl Force for Vortex {
+    fn force(&self, particle: &Particle, _time: f32) -> Vec2 {
+        let offset = particle.position - self.position;
+
+        offset.perp() * self.strength / (offset.length_squared() + self.softening * self.softening)
+    }
+}
+
+/// Slows particles down in proportion to their speed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Drag {
+    pub coefficient: f32,
+}
+
+impl Force for Drag {
+    fn force(&self, particle: &Particle, _time: f32) -> Vec2 {
+        -particle.velocity * self.coefficient
+    }
+}
+
+/// Pushes particles along a smooth, random flow field.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct NoiseField {
+    /// The size of the features of the field.
+    pub scale: f32,
+    pub strength: f32,
+    /// How fast the field changes over time. At `0.0` it stays the same.
+    pub evolution: f32,
+    /// Different seeds give unrelated fields.
+    pub seed: u32,
+}
+
+impl Force for NoiseField {
+    fn force(&self, particle: &Particle, time: f32) -> Vec2 {
+        let p =
+            particle.position / self.scale.max(f32::EPSILON) + Vec2::splat(time * self.evolution);
+
+        // Value noise rarely reaches its extremes, so it is stretched to turn all the way around.
+        let angle = value_noise(p, self.seed) * 4.0 * PI;
+
+        Vec2::new(angle.cos(), angle.sin()) * self.strength
+    }
+}
+
+/// Pushes particles along the brightness gradient of an image, towards bright areas for a positive strength.
+/// The image covers the canvas the same way input images of other sketchers do.
+#[derive(Clone)]
+pub struct ImageGradient {
+    transform: PixelTransform,
+    width: usize,
+    height: usize,
+    gradients: Vec<Vec2>,
+    strength: f32,
+}
+
+impl ImageGradient {
+    /// Computes the gradient of an image, after a box blur with a given radius.
+    pub fn new(image: &RasterCanvas, blur_radius: usize, strength: f32) -> Self {
+        let width = image.width();
+        let height = image.height();
+
+        Self {
+            transform: PixelTransform::new(width, height),
+            width,
+            height,
+            gradients: (0..width * height)
+                .map(|i| {
+                    let gradient = image.get_gradient(i % width, i / width, blur_radius);
+
+                    // Pixel space has y pointing down.
+                    Vec2::new(gradient.x, -gradient.y)
+                })
+                .collect(),
+            strength,
+        }
+    }
+}
+
+impl Force for ImageGradient {
+    fn force(&self, particle: &Particle, _time: f32) -> Vec2 {
+        let p = self.transform.invert(particle.position);
+
+        if p.x < 0.0 || p.y < 0.0 || p.x >= self.width as f32 || p.y >= self.height as f32 {
+            return Vec2::ZERO;
+        }
+
+        self.gradients[p.y as usize * self.width + p.x as usize] * self.strength
+    }
+}
+
+#[derive(Clone)]
+pub struct ParticleSketcherSettings {
+    /// The number of particles to simulate.
+    pub particle_count: usize,
+    /// The corner of the area particles start in with the lowest coordinates.
+    pub spawn_min: Vec2,
+    /// The corner of the area particles start in with the highest coordinates.
+    pub spawn_max: Vec2,
+    /// The speed particles start with, in a random direction.
+    pub initial_speed: f32,
+    /// The forces acting on the particles, which are added together.
+    pub forces: Vec<Arc<dyn Force>>,
+    /// If set, particles are slowed down to this speed.
+    pub max_speed: Option<f32>,
+    /// The number of steps to simulate.
+    pub steps: usize,
+    /// How much time should pass between steps.
+    pub step_length: f32,
+    /// The stroke to draw trails with.
+    pub stroke: Stroke,
+    /// If set, colors each trail by the particle's average speed, relative to the fastest particle, instead of the stroke color.
+    pub stroke_colors: Option<ColorMap>,
+}
+
+/// Moves particles under a combination of forces, drawing their trails.
+pub struct ParticleSketcher {
+    settings: ParticleSketcherSettings,
+    particles: Vec<(Particle, Vec<Vec2>)>,
+    time: f32,
+    canvas: VectorCanvas,
+}
+
+impl ParticleSketcher {
+    pub fn new(
+        settings: ParticleSketcherSettings,
+        #[cfg(feature = "small-rng")] seed: u64,
+    ) -> Self {
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let mut rng = SmallRng::seed_from_u64(seed);
+
+        let particles = (0..settings.particle_count)
+            .map(|_| {
+                let position = settings.spawn_min
+                    + (settings.spawn_max - settings.spawn_min)
+                        * Vec2::new(rng.gen::<f32>(), rng.gen::<f32>());
+                let angle = rng.gen_range(0.0..2.0 * PI);
+
+                (
+                    Particle {
+                        position,
+                        velocity: Vec2::new(angle.cos(), angle.sin()) * settings.initial_speed,
+                    },
+                    Vec::with_capacity(settings.steps + 1),
+                )
+            })
+            .collect();
+
+        Self {
+            settings,
+            particles,
+            time: 0.0,
+            canvas: VectorCanvas::default(),
+        }
+    }
+
+    /// Computes the next step of the simulation.
+    fn step(&mut self) {
+        let settings = &self.settings;
+        let time = self.time;
+
+        let update = |(particle, path): &mut (Particle, Vec<Vec2>)| {
+            if path.is_empty() {
+                path.push(particle.position);
+            }
+
+            let acceleration = settings
+                .forces
+                .iter()
+                .fold(Vec2::ZERO, |sum, force| sum + force.force(particle, time));
+
+            particle.velocity += acceleration * settings.step_length;
+
+            if let Some(max_speed) = settings.max_speed {
+                particle.velocity = particle.velocity.clamp_length_max(max_speed);
+            }
+
+            particle.position += particle.velocity * settings.step_length;
+            path.push(particle.position);
+        };
+
+        #[cfg(feature = "parallel")]
+        self.particles.par_iter_mut().for_each(update);
+        #[cfg(not(feature = "parallel"))]
+        self.particles.iter_mut().for_each(update);
+
+        self.time += self.settings.step_length;
+    }
+
+    /// Renders the trail of every particle.
+    fn render(&mut self) {
+        self.canvas = VectorCanvas::default();
+
+        // The length of a trail is proportional to the average speed of its particle.
+        let lengths: Vec<f32> = self
+            .particles
+            .iter()
+            .map(|(_, path)| path.windows(2).map(|w| w[0].distance(w[1])).sum())
+            .collect();
+        let longest = lengths.iter().copied().fold(f32::EPSILON, f32::max);
+
+        for ((_, path), length) in self.particles.iter().zip(lengths) {
+            let stroke = map_stroke(
+                self.settings.stroke,
+                self.settings.stroke_colors.as_ref(),
+                length / longest,
+            );
+
+            self.canvas.draw_shape(path.clone(), Some(stroke), None);
+        }
+    }
+
+    /// Simulates and renders the particles.
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        for i in 0..self.settings.steps {
+            before_iter(i as f32 / self.settings.steps as f32);
+
+            self.step();
+        }
+
+        self.render();
+    }
+}
+
+impl<P> Sketcher<P> for ParticleSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
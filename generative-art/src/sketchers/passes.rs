@@ -0,0 +1,562 @@
+use std::cell::RefCell;
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use barium::{Color, LineEnd, Mat2, Shape, Stroke, Vec2};
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+
+use super::{Sketcher, VectorFragmentSketcher};
+use crate::canvas::{OmniCanvas, VectorCanvas, VectorizerStyle};
+use crate::helpers::{hatch_lines, hatch_rows, inset_convex_polygon, value_noise};
+use crate::palette::{ColorMap, Palette};
+
+/// How [VectorPass::Hatch] fills shapes with lines.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HatchStyle {
+    /// Parallel lines, clipped to the shape.
+    Lines,
+    /// Parallel lines in two directions, at right angles. Each direction is twice the spacing apart, so the tone matches [HatchStyle::Lines].
+    CrossHatch,
+    /// Copies of the outline, each moved inwards by the spacing, until the shape is used up. The angle is not used.
+    /// Edges are moved along as straight lines, so this suits convex shapes best.
+    Concentric,
+    /// Parallel lines joined end to end, going back and forth across the shape in as few paths as possible.
+    ZigZag,
+}
+
+/// A reusable operation over the shapes of a [VectorCanvas], run by a [VectorPassSketcher].
+///
+/// Shapes with a fill are treated as closed polygons, and shapes without one as open paths.
+#[derive(Clone)]
+pub enum VectorPass {
+    /// Transforms every point by a matrix, then moves it by a translation.
+    Affine { matrix: Mat2, translation: Vec2 },
+    /// Moves every point by a random offset of up to `amount`.
+    Jitter { amount: f32 },
+    /// Moves every point along smooth noise, so that lines look hand-drawn rather than shaken.
+    /// `scale` is the size of the features of the noise.
+    Wobble { amount: f32, scale: f32, seed: u32 },
+    /// Removes points with the Douglas–Peucker algorithm, keeping the path within `tolerance` of the original.
+    DouglasPeucker { tolerance: f32 },
+    /// Removes points with the Visvalingam–Whyatt algorithm, until every remaining point forms a triangle
+    /// of at least `min_area` with its neighbours.
+    Visvalingam { min_area: f32 },
+    /// Rounds off corners by cutting them a number of times with Chaikin's algorithm.
+    Chaikin { iterations: usize },
+    /// Replaces the points of each path with points evenly spaced along it.
+    Resample { spacing: f32 },
+    /// Snaps fill and stroke colors to the closest color of a palette.
+    Palette(Palette),
+    /// Replaces fill and stroke colors by sampling a colormap at their brightness, keeping their alpha.
+    ColorMap(ColorMap),
+    /// Replaces each fill with lines in the color of the fill, which a pen plotter can draw.
+    /// The outline of the shape is kept (after the lines) if it has a stroke, and shapes without a fill are left alone.
+    ///
+    /// This turns each shape into several, so it is only run by [VectorPassSketcher] and [VectorPass::expand].
+    Hatch {
+        style: HatchStyle,
+        /// The distance between lines.
+        spacing: f32,
+        /// The angle of the lines, in radians.
+        angle: f32,
+        /// The width of the lines.
+        width: f32,
+        /// Whether to space the lines by the brightness of each fill, so that its tone is kept.
+        /// Black fills get lines `spacing` apart, lighter fills get them further apart, and white fills get none.
+        tone: bool,
+    },
+}
+
+impl VectorPass {
+    /// Creates a pass that rotates (in radians), scales and then moves every point.
+    pub fn transform(rotation: f32, scale: f32, translation: Vec2) -> Self {
+        Self::Affine {
+            matrix: Mat2::from_angle(rotation) * scale,
+            translation,
+        }
+    }
+
+    /// Applies the pass to a single shape.
+    /// This allows passes to be used from the kernel of a [VectorFragmentSketcher] directly.
+    pub fn apply<R: Rng + ?Sized>(&self, shape: &mut Shape, rng: &mut R) {
+        let closed = shape.fill.is_some();
+        let min_points = if closed { 3 } else { 2 };
+
+        match self {
+            Self::Affine {
+                matrix,
+                translation,
+            } => {
+                for point in &mut shape.points {
+                    *point = matrix.mul_vec2(*point) + *translation;
+                }
+            }
+            Self::Jitter { amount } => {
+                for point in &mut shape.points {
+                    // Taking the square root of the distance spreads offsets evenly over the disc.
+                    let angle = rng.gen_range(0.0..TAU);
+                    let distance = rng.gen::<f32>().sqrt() * amount;
+
+                    *point += Vec2::new(angle.cos(), angle.sin()) * distance;
+                }
+            }
+            Self::Wobble {
+                amount,
+                scale,
+                seed,
+            } => {
+                let scale = scale.max(f32::EPSILON);
+
+                for point in &mut shape.points {
+                    let p = *point / scale;
+                    let offset = Vec2::new(
+                        value_noise(p, *seed) * 2.0 - 1.0,
+                        value_noise(p, seed.wrapping_add(1)) * 2.0 - 1.0,
+                    );
+
+                    *point += offset * *amount;
+                }
+            }
+            Self::DouglasPeucker { tolerance } => {
+                if shape.points.len() > min_points {
+                    shape.points = douglas_peucker(&shape.points, *tolerance, closed);
+                }
+            }
+            Self::Visvalingam { min_area } => {
+                if shape.points.len() > min_points {
+                    shape.points = visvalingam(&shape.points, *min_area, closed, min_points);
+                }
+            }
+            Self::Chaikin { iterations } => {
+                for _ in 0..*iterations {
+                    if shape.points.len() < min_points {
+                        break;
+                    }
+
+                    shape.points = chaikin(&shape.points, closed);
+                }
+            }
+            Self::Resample { spacing } => {
+                if shape.points.len() >= 2 && *spacing > 0.0 {
+                    shape.points = resample(&shape.points, *spacing, closed);
+                }
+            }
+            Self::Palette(palette) => {
+                if let Some(fill) = &mut shape.fill {
+                    *fill = palette.nearest(*fill);
+                }
+                if let Some(stroke) = &mut shape.stroke {
+                    stroke.color = palette.nearest(stroke.color);
+                }
+            }
+            Self::ColorMap(colormap) => {
+                let remap = |color: Color| {
+                    let mut mapped = colormap.sample((color.r() + color.g() + color.b()) / 3.0);
+                    *mapped.a_mut() *= color.a();
+
+                    mapped
+                };
+
+                if let Some(fill) = &mut shape.fill {
+                    *fill = remap(*fill);
+                }
+                if let Some(stroke) = &mut shape.stroke {
+                    stroke.color = remap(stroke.color);
+                }
+            }
+            // Turning one shape into many is handled by `expand`.
+            Self::Hatch { .. } => (),
+        }
+    }
+
+    /// Applies the pass to a single shape, which some passes (like [VectorPass::Hatch]) turn into several.
+    pub fn expand<R: Rng + ?Sized>(&self, mut shape: Shape, rng: &mut R) -> Vec<Shape> {
+        match *self {
+            Self::Hatch {
+                style,
+                spacing,
+                angle,
+                width,
+                tone,
+            } => hatch(shape, style, spacing, angle, width, tone),
+            _ => {
+                self.apply(&mut shape, rng);
+
+                vec![shape]
+            }
+        }
+    }
+
+    /// Whether the pass can turn a shape into several.
+    fn expands(&self) -> bool {
+        matches!(self, Self::Hatch { .. })
+    }
+}
+
+/// Runs a chain of [VectorPasses](VectorPass) over the shapes of a [VectorCanvas], one after another.
+/// Progress is split evenly between the passes.
+pub struct VectorPassSketcher {
+    canvas: VectorCanvas,
+    passes: Vec<VectorPass>,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+impl VectorPassSketcher {
+    pub fn new(
+        canvas: VectorCanvas,
+        passes: Vec<VectorPass>,
+        #[cfg(feature = "small-rng")] seed: u64,
+    ) -> Self {
+        Self {
+            canvas,
+            passes,
+            #[cfg(feature = "small-rng")]
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Adds a pass to the end of the chain.
+    pub fn then(mut self, pass: VectorPass) -> Self {
+        self.passes.push(pass);
+        self
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        let pass_count = self.passes.len();
+
+        #[cfg(feature = "small-rng")]
+        let rng = RefCell::new(&mut self.rng);
+
+        for (i, pass) in self.passes.iter().enumerate() {
+            // Kernels can't borrow the random number generator mutably, so it is shared through a cell.
+            #[cfg(feature = "thread-rng")]
+            let rng = RefCell::new(rand::thread_rng());
+
+            if pass.expands() {
+                let shapes = std::mem::take(self.canvas.as_raw_mut());
+                let shape_count = shapes.len();
+
+                for (j, shape) in shapes.into_iter().enumerate() {
+                    before_iter((i as f32 + j as f32 / shape_count as f32) / pass_count as f32);
+
+                    let expanded = pass.expand(shape, &mut *rng.borrow_mut());
+                    self.canvas.as_raw_mut().extend(expanded);
+                }
+
+                continue;
+            }
+
+            let kernel = |shape: &mut Shape| pass.apply(shape, &mut *rng.borrow_mut());
+
+            let fragment = VectorFragmentSketcher::new(std::mem::take(&mut self.canvas), kernel);
+
+            self.canvas = fragment
+                .run_and_dispose(|progress| before_iter((i as f32 + progress) / pass_count as f32))
+                .into_vector_canvas(VectorizerStyle::Pixels);
+        }
+    }
+}
+
+impl<P> Sketcher<P> for VectorPassSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
+
+fn hatch(
+    shape: Shape,
+    style: HatchStyle,
+    spacing: f32,
+    angle: f32,
+    width: f32,
+    tone: bool,
+) -> Vec<Shape> {
+    let Some(fill) = shape.fill else {
+        return vec![shape];
+    };
+
+    let spacing = if tone {
+        spacing / (1.0 - (fill.r() + fill.g() + fill.b()) / 3.0)
+    } else {
+        spacing
+    };
+
+    let mut paths: Vec<Vec<Vec2>> = Vec::new();
+
+    // White fills (with tone) have an infinite spacing, and get no lines.
+    if spacing.is_finite() && spacing > 0.0 {
+        let points = &shape.points;
+
+        match style {
+            HatchStyle::Lines => paths.extend(
+                hatch_lines(points, spacing, angle)
+                    .into_iter()
+                    .map(|(a, b)| vec![a, b]),
+            ),
+            HatchStyle::CrossHatch => {
+                for angle in [angle, angle + FRAC_PI_2] {
+                    paths.extend(
+                        hatch_lines(points, spacing * 2.0, angle)
+                            .into_iter()
+                            .map(|(a, b)| vec![a, b]),
+                    );
+                }
+            }
+            HatchStyle::Concentric => {
+                let (min, max) = points.iter().fold(
+                    (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+                    |(min, max), p| (min.min(*p), max.max(*p)),
+                );
+
+                // Nothing can be left once the outline has moved in by half the size of the shape.
+                let mut distance = spacing / 2.0;
+
+                while distance < (max - min).max_element() / 2.0 {
+                    let Some(mut ring) = inset_convex_polygon(points, distance) else {
+                        break;
+                    };
+
+                    ring.push(ring[0]);
+                    paths.push(ring);
+
+                    distance += spacing;
+                }
+            }
+            HatchStyle::ZigZag => {
+                let direction = Vec2::new(angle.cos(), angle.sin());
+                let extent = |(a, b): (Vec2, Vec2)| {
+                    let (a, b) = (a.dot(direction), b.dot(direction));
+
+                    (a.min(b), a.max(b))
+                };
+
+                let mut previous: Option<(Vec2, Vec2)> = None;
+
+                for row in hatch_rows(points, spacing, angle) {
+                    let single = row.len() == 1;
+
+                    for line in row {
+                        // Only lines that overlap along the hatching can be joined without leaving the shape,
+                        // and only where neither row is split by a gap in the shape.
+                        let joins = single
+                            && previous.is_some_and(|previous| {
+                                let (a, b) = (extent(previous), extent(line));
+
+                                a.0 <= b.1 && b.0 <= a.1
+                            });
+
+                        match paths.last_mut() {
+                            Some(path) if joins => path.extend([line.0, line.1]),
+                            _ => paths.push(vec![line.0, line.1]),
+                        }
+
+                        previous = single.then_some(line);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut shapes: Vec<Shape> = paths
+        .into_iter()
+        .map(|points| Shape {
+            points,
+            stroke: Some(Stroke {
+                color: fill,
+                width,
+                line_end: LineEnd::Round,
+            }),
+            fill: None,
+        })
+        .collect();
+
+    if let Some(stroke) = shape.stroke {
+        let mut outline = shape.points;
+
+        if let Some(first) = outline.first().copied() {
+            outline.push(first);
+        }
+
+        shapes.push(Shape {
+            points: outline,
+            stroke: Some(stroke),
+            fill: None,
+        });
+    }
+
+    shapes
+}
+
+/// The distance from a point to the segment between two others.
+fn segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+
+    p.distance(a + ab * t)
+}
+
+fn douglas_peucker(points: &[Vec2], tolerance: f32, closed: bool) -> Vec<Vec2> {
+    // Closed paths are split at the point furthest from the first, so that both halves have distinct ends.
+    if closed {
+        let far = (1..points.len())
+            .max_by(|a, b| {
+                points[*a]
+                    .distance_squared(points[0])
+                    .total_cmp(&points[*b].distance_squared(points[0]))
+            })
+            .unwrap_or(1);
+
+        let mut ring = points.to_vec();
+        ring.push(points[0]);
+
+        let mut simplified = douglas_peucker(&ring[..=far], tolerance, false);
+        simplified.pop();
+        simplified.extend(douglas_peucker(&ring[far..], tolerance, false));
+        simplified.pop();
+
+        if simplified.len() < 3 {
+            return points.to_vec();
+        }
+
+        return simplified;
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    let mut stack = vec![(0, points.len() - 1)];
+
+    while let Some((start, end)) = stack.pop() {
+        let furthest = (start + 1..end)
+            .map(|i| (i, segment_distance(points[i], points[start], points[end])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((i, distance)) = furthest {
+            if distance > tolerance {
+                keep[i] = true;
+                stack.push((start, i));
+                stack.push((i, end));
+            }
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(point, _)| *point)
+        .collect()
+}
+
+fn visvalingam(points: &[Vec2], min_area: f32, closed: bool, min_points: usize) -> Vec<Vec2> {
+    let mut points = points.to_vec();
+
+    let area = |points: &[Vec2], i: usize| {
+        let n = points.len();
+        let (previous, next) = ((i + n - 1) % n, (i + 1) % n);
+
+        (points[previous] - points[i])
+            .perp_dot(points[next] - points[i])
+            .abs()
+            / 2.0
+    };
+
+    // Open paths keep their ends.
+    while points.len() > min_points {
+        let candidates = if closed {
+            0..points.len()
+        } else {
+            1..points.len() - 1
+        };
+
+        let smallest = candidates
+            .map(|i| (i, area(&points, i)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        match smallest {
+            Some((i, area)) if area < min_area => {
+                points.remove(i);
+            }
+            _ => break,
+        }
+    }
+
+    points
+}
+
+fn chaikin(points: &[Vec2], closed: bool) -> Vec<Vec2> {
+    let n = points.len();
+    let segments = if closed { n } else { n - 1 };
+
+    let mut smoothed = Vec::with_capacity(segments * 2 + 2);
+
+    if !closed {
+        smoothed.push(points[0]);
+    }
+
+    for i in 0..segments {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+
+        smoothed.push(a.lerp(b, 0.25));
+        smoothed.push(a.lerp(b, 0.75));
+    }
+
+    if !closed {
+        smoothed.push(points[n - 1]);
+    }
+
+    smoothed
+}
+
+fn resample(points: &[Vec2], spacing: f32, closed: bool) -> Vec<Vec2> {
+    let mut path = points.to_vec();
+    if closed {
+        path.push(points[0]);
+    }
+
+    let length: f32 = path.windows(2).map(|pair| pair[0].distance(pair[1])).sum();
+    let count = ((length / spacing).round() as usize).max(1);
+
+    // The spacing is stretched slightly so that the last point lands on the end of the path.
+    let step = length / count as f32;
+
+    let mut resampled = vec![path[0]];
+    let mut segment = 0;
+    let mut travelled = 0.0;
+
+    for i in 1..count {
+        let target = step * i as f32;
+
+        while segment < path.len() - 2
+            && travelled + path[segment].distance(path[segment + 1]) < target
+        {
+            travelled += path[segment].distance(path[segment + 1]);
+            segment += 1;
+        }
+
+        let (a, b) = (path[segment], path[segment + 1]);
+        let t = (target - travelled) / a.distance(b).max(f32::EPSILON);
+
+        resampled.push(a.lerp(b, t.clamp(0.0, 1.0)));
+    }
+
+    if !closed {
+        resampled.push(path[path.len() - 1]);
+    }
+
+    resampled
+}
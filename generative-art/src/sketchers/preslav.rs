@@ -1,3 +1,5 @@
+use std::f32::consts::PI;
+
 use barium::{Color, LineEnd, Mat2, Stroke, Vec2};
 
 use rand::{prelude::Distribution, Rng};
@@ -5,7 +7,83 @@ use rand::{prelude::Distribution, Rng};
 use rand::{rngs::SmallRng, SeedableRng};
 
 use super::Sketcher;
-use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::canvas::{blend, OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::helpers::{cumulative, polygon_spans, sample_cumulative, PixelTransform};
+
+/// The shape each stroke of a [PreslavSketcher] is drawn with.
+#[derive(Clone)]
+pub enum PreslavShape {
+    /// A regular polygon, with a number of edges picked from [edge_count](PreslavSketcherSettings::edge_count).
+    RegularPolygon,
+    /// An ellipse, stretched along its rotation by an aspect ratio.
+    Ellipse { aspect_ratio: f32 },
+    /// An elongated brush stroke with a rounded head and a tapered tail, stretched along its rotation by an aspect ratio.
+    BrushStroke { aspect_ratio: f32 },
+    /// A custom polygon in the space -1..1, which is scaled by the stroke size and rotated with the stroke.
+    Custom { points: Vec<Vec2> },
+}
+
+impl PreslavShape {
+    /// The number of points used to approximate curved shapes.
+    const CURVE_RESOLUTION: usize = 24;
+
+    /// Creates the outline of the shape, centered at the origin with a radius of one and pointing along the x axis.
+    /// Returns [None] for [RegularPolygon](Self::RegularPolygon), which is drawn directly.
+    fn outline(&self) -> Option<Vec<Vec2>> {
+        match self {
+            PreslavShape::RegularPolygon => None,
+            PreslavShape::Ellipse { aspect_ratio } => Some(
+                (0..Self::CURVE_RESOLUTION)
+                    .map(|i| {
+                        let theta = i as f32 / Self::CURVE_RESOLUTION as f32 * 2.0 * PI;
+
+                        Vec2::new(theta.cos(), theta.sin() / aspect_ratio)
+                    })
+                    .collect(),
+            ),
+            PreslavShape::BrushStroke { aspect_ratio } => {
+                let half = Self::CURVE_RESOLUTION / 2;
+
+                // Run along the top edge from head to tail, then back along the bottom edge.
+                let top = (0..=half).map(|i| {
+                    let t = 1.0 - i as f32 / half as f32 * 2.0;
+                    let taper = 1.0 - 0.4 * (1.0 - t) / 2.0;
+
+                    Vec2::new(t, (1.0 - t * t).max(0.0).powf(0.35) * taper / aspect_ratio)
+                });
+
+                let top: Vec<Vec2> = top.collect();
+                let bottom: Vec<Vec2> = top[1..half]
+                    .iter()
+                    .rev()
+                    .map(|p| Vec2::new(p.x, -p.y))
+                    .collect();
+
+                Some(top.into_iter().chain(bottom).collect())
+            }
+            PreslavShape::Custom { points } => Some(points.clone()),
+        }
+    }
+}
+
+/// How a [PreslavSketcher] picks where to place each shape.
+#[derive(Clone)]
+pub enum PreslavPlacement {
+    /// Anywhere on the input image, with equal probability.
+    Uniform,
+    /// More often where the edges of the input image are strong.
+    /// Edges are found using [edge_blur_radius](PreslavSketcherSettings::edge_blur_radius).
+    Edges,
+    /// More often where a mask is bright. The mask is stretched to cover the input image.
+    Mask(RasterCanvas),
+    /// Wherever the shapes drawn so far differ most from the input image.
+    ///
+    /// A raster approximation of the drawing is kept, starting out as the background color.
+    /// It is split into square cells of the given size (in pixels), and each shape is placed in the cell with the most error.
+    /// Shapes that wouldn't lower the error (like in fine texture, or once they are smaller than a pixel) are skipped,
+    /// and their cell is passed over for a while, so that shapes don't pile up where they can't help.
+    Greedy { cell_size: usize, background: Color },
+}
 
 #[derive(Clone)]
 pub struct PreslavSketcherSettings<E>
@@ -28,11 +106,24 @@ where
     /// They are rendered back to front, so these will be the background.
     pub stroke_size: f32,
     /// How much smaller the stroke should become each time one is drawn.
+    /// Not used when [detail_sizing](Self::detail_sizing) is set, since the size then comes from local detail instead.
     pub stroke_reduction: f32,
     /// Randomize rotation multiplier
     pub randomize_rotation: f32,
     /// The number of shapes to render.
     pub shapes: usize,
+    /// The shape to draw each stroke with.
+    pub shape: PreslavShape,
+    /// Rotate each shape to run along the edges of the input image, instead of from zero.
+    /// [randomize_rotation](Self::randomize_rotation) is still applied on top, centered around the edge direction.
+    pub follow_edges: bool,
+    /// How much local detail (the strength of edges) in the input image shrinks each shape. For no effect, set this to zero.
+    /// When set, shapes are sized from [stroke_size](Self::stroke_size) by local detail alone, rather than also shrinking with each one drawn.
+    pub detail_sizing: f32,
+    /// Radius of a box blur applied to the input image before finding edges. For no box blur, set this to zero.
+    pub edge_blur_radius: usize,
+    /// How to pick where each shape is placed.
+    pub placement: PreslavPlacement,
 }
 
 /// Art generator based on Preslav's Book *Generative Art in Go*
@@ -41,8 +132,10 @@ where
     E: Distribution<usize> + Clone,
 {
     settings: PreslavSketcherSettings<E>,
-    half_size: Vec2,
-    scale_mat: Mat2,
+    transform: PixelTransform,
+    /// Cumulative distribution of the pixels shapes can be placed on, if not placed uniformly.
+    importance: Option<Vec<f32>>,
+    error_map: Option<ErrorMap>,
     input_image: RasterCanvas,
     canvas: VectorCanvas,
     #[cfg(feature = "small-rng")]
@@ -58,15 +151,39 @@ where
         settings: PreslavSketcherSettings<E>,
         #[cfg(feature = "small-rng")] seed: u64,
     ) -> Self {
-        let major_dimension = usize::min(input_image.width(), input_image.height()) as f32;
+        let width = input_image.width();
+        let height = input_image.height();
+
+        let importance = match &settings.placement {
+            PreslavPlacement::Edges => Some(cumulative((0..width * height).map(|i| {
+                // Keep a small chance of placing shapes in flat areas.
+                input_image
+                    .get_gradient(i % width, i / width, settings.edge_blur_radius)
+                    .length()
+                    + 1e-4
+            }))),
+            PreslavPlacement::Mask(mask) => Some(cumulative((0..width * height).map(|i| {
+                mask.get_brightness(
+                    i % width * mask.width() / width,
+                    i / width * mask.height() / height,
+                )
+            }))),
+            _ => None,
+        };
+
+        let error_map = match settings.placement {
+            PreslavPlacement::Greedy {
+                cell_size,
+                background,
+            } => Some(ErrorMap::new(&input_image, cell_size, background)),
+            _ => None,
+        };
 
         Self {
             settings,
-            half_size: Vec2::new(input_image.width() as f32, input_image.height() as f32) / 2.0,
-            scale_mat: Mat2::from_cols(
-                Vec2::X * (2.0 / major_dimension),
-                Vec2::Y * (-2.0 / major_dimension),
-            ),
+            transform: PixelTransform::new(width, height),
+            importance,
+            error_map,
             input_image,
             canvas: VectorCanvas::default(),
             #[cfg(feature = "small-rng")]
@@ -86,19 +203,39 @@ where
         #[cfg(feature = "small-rng")]
         let mut rng = &mut self.rng;
 
-        let p = Vec2::new(
-            rng.gen_range(0.0..(self.input_image.width() as f32)),
-            rng.gen_range(0.0..(self.input_image.height() as f32)),
-        );
+        // There is nowhere to place shapes on an empty image.
+        if self.input_image.width() == 0 || self.input_image.height() == 0 {
+            return;
+        }
+
+        let cell = self.error_map.as_mut().and_then(ErrorMap::worst_cell);
+
+        let p = if let (Some(cell), Some(error_map)) = (cell, &self.error_map) {
+            let (min, max) = error_map.cell_bounds(cell);
+
+            Vec2::new(rng.gen_range(min.x..max.x), rng.gen_range(min.y..max.y))
+        } else if let Some(importance) = &self.importance {
+            let i = sample_cumulative(importance, &mut rng);
+            let width = self.input_image.width();
+
+            Vec2::new(
+                (i % width) as f32 + rng.gen::<f32>(),
+                (i / width) as f32 + rng.gen::<f32>(),
+            )
+        } else {
+            Vec2::new(
+                rng.gen_range(0.0..(self.input_image.width() as f32)),
+                rng.gen_range(0.0..(self.input_image.height() as f32)),
+            )
+        };
 
         let jitter = Vec2::new(
             rng.gen_range(-self.settings.stroke_jitter..self.settings.stroke_jitter),
             rng.gen_range(-self.settings.stroke_jitter..self.settings.stroke_jitter),
         );
 
-        // TODO: Clean this up.
         // Transform from input image pixel coordinates to canvas coordinates, then apply jitter.
-        let d = self.scale_mat.mul_vec2(p - self.half_size) + jitter;
+        let d = self.transform.apply(p) + jitter;
 
         let edge_count = (&self.settings.edge_count).sample(&mut rng);
         let mut color: Color = self.input_image.get_pixel(p.x as usize, p.y as usize);
@@ -122,16 +259,75 @@ where
             None
         };
 
-        self.canvas.draw_regular_polygon(
-            d,
-            edge_count,
-            self.settings.stroke_size,
-            rng.gen::<f32>() * self.settings.randomize_rotation,
-            edge_color,
-            Some(color),
-        );
+        let gradient = if self.settings.follow_edges || self.settings.detail_sizing != 0.0 {
+            self.input_image.get_gradient(
+                p.x as usize,
+                p.y as usize,
+                self.settings.edge_blur_radius,
+            )
+        } else {
+            Vec2::ZERO
+        };
+
+        let rotation = if self.settings.follow_edges {
+            // Edges run perpendicular to the gradient. The y axis is flipped, since the gradient is in pixel space.
+            let edge = gradient.perp();
+
+            (-edge.y).atan2(edge.x) + (rng.gen::<f32>() - 0.5) * self.settings.randomize_rotation
+        } else {
+            rng.gen::<f32>() * self.settings.randomize_rotation
+        };
+
+        let size =
+            self.settings.stroke_size / (1.0 + self.settings.detail_sizing * gradient.length());
+
+        let outline = self.settings.shape.outline().map(|outline| {
+            let transform = Mat2::from_angle(rotation) * size;
 
-        self.settings.stroke_size -= self.settings.stroke_reduction * self.settings.stroke_size;
+            outline
+                .into_iter()
+                .map(|v| d + transform.mul_vec2(v))
+                .collect::<Vec<_>>()
+        });
+
+        let mut improves = true;
+
+        if let Some(error_map) = &mut self.error_map {
+            let points: Vec<Vec2> = match &outline {
+                Some(outline) => outline.clone(),
+                None => regular_polygon(d, edge_count, size, rotation),
+            };
+            let points: Vec<Vec2> = points
+                .into_iter()
+                .map(|v| self.transform.invert(v))
+                .collect();
+
+            // Like the primitive sketcher, only draw shapes that actually improve the drawing.
+            improves = error_map.error_change(&self.input_image, &points, color) < 0.0;
+
+            if improves {
+                error_map.draw(&self.input_image, &points, color);
+            } else if let Some(cell) = cell {
+                error_map.rest(cell);
+            }
+        }
+
+        match outline {
+            _ if !improves => (),
+            None => self.canvas.draw_regular_polygon(
+                d,
+                edge_count,
+                size,
+                rotation,
+                edge_color,
+                Some(color),
+            ),
+            Some(outline) => self.canvas.draw_shape(outline, edge_color, Some(color)),
+        }
+
+        if self.settings.detail_sizing == 0.0 {
+            self.settings.stroke_size -= self.settings.stroke_reduction * self.settings.stroke_size;
+        }
         self.settings.alpha += self.settings.alpha_increase / self.settings.alpha;
         self.settings.alpha = self.settings.alpha.clamp(0.0, 1.0);
     }
@@ -143,7 +339,7 @@ where
 
             self.draw_shape();
         }
-        }
+    }
 }
 
 impl<E, F> Sketcher<F> for PreslavSketcher<E>
@@ -163,3 +359,172 @@ where
         self.canvas.into()
     }
 }
+
+/// Creates the points of a regular polygon.
+fn regular_polygon(center: Vec2, sides: usize, radius: f32, rotation: f32) -> Vec<Vec2> {
+    (0..sides)
+        .map(|i| {
+            let theta = rotation + i as f32 / sides as f32 * 2.0 * PI;
+
+            center + Vec2::new(theta.cos(), theta.sin()) * radius
+        })
+        .collect()
+}
+
+/// A raster approximation of the drawing, used to find where it differs most from the input image.
+struct ErrorMap {
+    approximation: RasterCanvas,
+    cell_size: usize,
+    columns: usize,
+    rows: usize,
+    /// The total squared error of each cell.
+    errors: Vec<f32>,
+    /// The number of cells picked so far.
+    attempts: usize,
+    /// The attempt each cell can be picked again from, if it is resting.
+    resting_until: Vec<usize>,
+}
+
+impl ErrorMap {
+    /// How many attempts a cell is passed over for, after a shape placed in it didn't lower the error.
+    const REST_ATTEMPTS: usize = 32;
+
+    fn new(target: &RasterCanvas, cell_size: usize, background: Color) -> Self {
+        let cell_size = cell_size.max(1);
+
+        let mut approximation = RasterCanvas::new(target.width(), target.height());
+        approximation.get_raw_mut().fill(background);
+
+        let columns = target.width().div_ceil(cell_size);
+        let rows = target.height().div_ceil(cell_size);
+
+        let mut error_map = Self {
+            approximation,
+            cell_size,
+            columns,
+            rows,
+            errors: vec![0.0; columns * rows],
+            attempts: 0,
+            resting_until: vec![0; columns * rows],
+        };
+
+        for row in 0..rows {
+            for column in 0..columns {
+                error_map.update_cell(target, column, row);
+            }
+        }
+
+        error_map
+    }
+
+    /// Recomputes the error of a single cell.
+    fn update_cell(&mut self, target: &RasterCanvas, column: usize, row: usize) {
+        let mut error = 0.0;
+
+        for y in row * self.cell_size..usize::min((row + 1) * self.cell_size, target.height()) {
+            for x in
+                column * self.cell_size..usize::min((column + 1) * self.cell_size, target.width())
+            {
+                error += pixel_error(target.get_pixel(x, y), self.approximation.get_pixel(x, y));
+            }
+        }
+
+        self.errors[row * self.columns + column] = error;
+    }
+
+    /// Finds the cell with the most error, passing over resting cells unless every cell is resting.
+    /// Each call counts as an attempt, for [rest](Self::rest). Returns [None] if there are no cells.
+    fn worst_cell(&mut self) -> Option<usize> {
+        self.attempts += 1;
+
+        let worst = |resting: bool| {
+            self.errors
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| resting || self.resting_until[*i] <= self.attempts)
+                .fold(
+                    None,
+                    |worst: Option<(usize, f32)>, (i, &error)| match worst {
+                        Some((_, worst_error)) if worst_error >= error => worst,
+                        _ => Some((i, error)),
+                    },
+                )
+                .map(|(i, _)| i)
+        };
+
+        worst(false).or_else(|| worst(true))
+    }
+
+    /// Passes over a cell for the next [REST_ATTEMPTS](Self::REST_ATTEMPTS) attempts.
+    fn rest(&mut self, cell: usize) {
+        self.resting_until[cell] = self.attempts + Self::REST_ATTEMPTS;
+    }
+
+    /// Finds the bounds of a cell, in pixel space.
+    fn cell_bounds(&self, i: usize) -> (Vec2, Vec2) {
+        let min = Vec2::new(
+            (i % self.columns * self.cell_size) as f32,
+            (i / self.columns * self.cell_size) as f32,
+        );
+        let max = Vec2::new(
+            usize::min(
+                (i % self.columns + 1) * self.cell_size,
+                self.approximation.width(),
+            ) as f32,
+            usize::min(
+                (i / self.columns + 1) * self.cell_size,
+                self.approximation.height(),
+            ) as f32,
+        );
+
+        (min, max)
+    }
+
+    /// Finds how much drawing a polygon (in pixel space) would change the total squared error, without drawing it.
+    fn error_change(&self, target: &RasterCanvas, points: &[Vec2], color: Color) -> f32 {
+        let mut change = 0.0;
+
+        for (y, columns) in polygon_spans(points, target.width(), target.height()) {
+            for x in columns {
+                let a = target.get_pixel(x, y);
+                let b = self.approximation.get_pixel(x, y);
+
+                change += pixel_error(a, blend(b, color)) - pixel_error(a, b);
+            }
+        }
+
+        change
+    }
+
+    /// Draws a polygon (in pixel space) onto the approximation, and updates the error of the cells it covers.
+    fn draw(&mut self, target: &RasterCanvas, points: &[Vec2], color: Color) {
+        self.approximation.fill_polygon(points, color);
+
+        let min = points
+            .iter()
+            .fold(Vec2::splat(f32::INFINITY), |a, &b| a.min(b));
+        let max = points
+            .iter()
+            .fold(Vec2::splat(f32::NEG_INFINITY), |a, &b| a.max(b));
+
+        if self.columns == 0 || self.rows == 0 || max.x < 0.0 || max.y < 0.0 {
+            return;
+        }
+
+        let start_column = usize::min(min.x.max(0.0) as usize / self.cell_size, self.columns - 1);
+        let end_column = usize::min(max.x as usize / self.cell_size, self.columns - 1);
+        let start_row = usize::min(min.y.max(0.0) as usize / self.cell_size, self.rows - 1);
+        let end_row = usize::min(max.y as usize / self.cell_size, self.rows - 1);
+
+        for row in start_row..=end_row {
+            for column in start_column..=end_column {
+                self.update_cell(target, column, row);
+            }
+        }
+    }
+}
+
+/// The squared error between the color channels of two pixels.
+fn pixel_error(a: Color, b: Color) -> f32 {
+    (a.r() - b.r()).powi(2) + (a.g() - b.g()).powi(2) + (a.b() - b.b()).powi(2)
+}
@@ -0,0 +1,476 @@
+use std::f32::consts::PI;
+
+use barium::{Color, Mat2, Vec2};
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::helpers::{polygon_spans, variable_width_outline, PixelTransform};
+
+/// A kind of primitive a [PrimitiveSketcher] can place.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PrimitiveShape {
+    Triangle,
+    Ellipse,
+    Rectangle,
+    RotatedRectangle,
+    /// A quadratic bezier curve, stroked with [stroke_width](PrimitiveSketcherSettings::stroke_width).
+    Bezier,
+}
+
+#[derive(Clone)]
+pub struct PrimitiveSketcherSettings {
+    /// The number of primitives to place.
+    pub shapes: usize,
+    /// The kinds of primitive to choose from. One is picked at random for each shape.
+    pub kinds: Vec<PrimitiveShape>,
+    /// The alpha of every primitive. This is in the space 0..1.
+    pub alpha: f32,
+    /// The number of random primitives to try before hill climbing from the best one.
+    pub candidates: usize,
+    /// How many mutations in a row can fail to improve a primitive before hill climbing stops.
+    pub max_age: usize,
+    /// How far (in pixels) a single mutation can move a point.
+    pub mutation_distance: f32,
+    /// The width (in pixels) of [Bezier](PrimitiveShape::Bezier) strokes.
+    pub stroke_width: f32,
+    /// The color drawn behind the primitives. If [None], the average color of the input image is used.
+    pub background: Option<Color>,
+}
+
+/// Approximates an image with primitives, each picked by random search and hill climbing to minimize the error.
+///
+/// Based on Michael Fogleman's [*Primitive*](https://github.com/fogleman/primitive).
+pub struct PrimitiveSketcher {
+    settings: PrimitiveSketcherSettings,
+    transform: PixelTransform,
+    input_image: RasterCanvas,
+    /// The raster approximation of the drawing so far.
+    current: RasterCanvas,
+    /// The total squared error between the approximation and the input image.
+    error: f32,
+    canvas: VectorCanvas,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+impl PrimitiveSketcher {
+    pub fn new(
+        input_image: RasterCanvas,
+        settings: PrimitiveSketcherSettings,
+        #[cfg(feature = "small-rng")] seed: u64,
+    ) -> Self {
+        let width = input_image.width();
+        let height = input_image.height();
+
+        let background = settings
+            .background
+            .unwrap_or_else(|| average_color(&input_image));
+
+        let mut current = RasterCanvas::new(width, height);
+        current.get_raw_mut().fill(background);
+
+        let error = input_image
+            .get_raw()
+            .iter()
+            .zip(current.get_raw())
+            .map(|(a, b)| pixel_error(*a, *b))
+            .sum();
+
+        let transform = PixelTransform::new(width, height);
+
+        let mut canvas = VectorCanvas::default();
+        canvas.draw_rect(
+            transform.apply(Vec2::new(0.0, height as f32)),
+            transform.apply(Vec2::new(width as f32, 0.0)),
+            None,
+            Some(background),
+        );
+
+        Self {
+            settings,
+            transform,
+            input_image,
+            current,
+            error,
+            canvas,
+            #[cfg(feature = "small-rng")]
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    /// The root-mean-square error between the drawing so far and the input image.
+    pub fn rms_error(&self) -> f32 {
+        let samples = self.input_image.width() * self.input_image.height() * 3;
+
+        (self.error / samples.max(1) as f32).sqrt()
+    }
+
+    /// Consumes the sketcher and returns the input image.
+    pub fn take_input(self) -> RasterCanvas {
+        self.input_image
+    }
+
+    /// Searches for the best next primitive, then draws it.
+    fn add_primitive(&mut self) {
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let mut rng = &mut self.rng;
+
+        if self.settings.kinds.is_empty() {
+            return;
+        }
+
+        let size = Vec2::new(
+            self.input_image.width() as f32,
+            self.input_image.height() as f32,
+        );
+
+        let candidates: Vec<Candidate> = (0..self.settings.candidates.max(1))
+            .map(|_| {
+                let kind = self.settings.kinds[rng.gen_range(0..self.settings.kinds.len())];
+
+                Candidate::random(kind, size, &mut rng)
+            })
+            .collect();
+
+        let context = ScoreContext {
+            target: &self.input_image,
+            current: &self.current,
+            alpha: self.settings.alpha,
+            stroke_width: self.settings.stroke_width,
+        };
+
+        #[cfg(feature = "parallel")]
+        let scored = candidates.into_par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let scored = candidates.into_iter();
+
+        let (mut best, mut best_score) = scored
+            .map(|candidate| {
+                let score = context.score(&candidate);
+                (candidate, score)
+            })
+            .min_by(|(_, (a, _)), (_, (b, _))| {
+                a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+
+        // Hill climb from the best candidate.
+        let mut age = 0;
+        while age < self.settings.max_age {
+            let mut mutated = best.clone();
+            mutated.mutate(size, self.settings.mutation_distance, &mut rng);
+
+            let score = context.score(&mutated);
+
+            if score.0 < best_score.0 {
+                best = mutated;
+                best_score = score;
+                age = 0;
+            } else {
+                age += 1;
+            }
+        }
+
+        let (delta, color) = best_score;
+
+        // Only draw primitives that actually improve the drawing.
+        if delta >= 0.0 {
+            return;
+        }
+
+        let polygon = best.polygon(self.settings.stroke_width);
+
+        self.current.fill_polygon(&polygon, color);
+        self.error += delta;
+
+        self.canvas.draw_shape(
+            polygon.iter().map(|p| self.transform.apply(*p)).collect(),
+            None,
+            Some(color),
+        );
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        // There is nowhere to place primitives on an empty image.
+        if self.input_image.width() == 0 || self.input_image.height() == 0 {
+            return;
+        }
+
+        for i in 0..self.settings.shapes {
+            before_iter(i as f32 / self.settings.shapes as f32);
+
+            self.add_primitive();
+        }
+    }
+}
+
+impl<P> Sketcher<P> for PrimitiveSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
+
+/// A primitive being searched for, in pixel space.
+#[derive(Clone, Debug)]
+enum Candidate {
+    Triangle([Vec2; 3]),
+    Ellipse {
+        center: Vec2,
+        radii: Vec2,
+        rotation: f32,
+    },
+    Rectangle {
+        a: Vec2,
+        b: Vec2,
+    },
+    RotatedRectangle {
+        center: Vec2,
+        size: Vec2,
+        rotation: f32,
+    },
+    Bezier([Vec2; 3]),
+}
+
+impl Candidate {
+    /// The number of points used to approximate curved primitives.
+    const CURVE_RESOLUTION: usize = 24;
+
+    fn random<R: Rng + ?Sized>(kind: PrimitiveShape, size: Vec2, rng: &mut R) -> Self {
+        let anchor = Vec2::new(rng.gen_range(0.0..size.x), rng.gen_range(0.0..size.y));
+
+        // Start primitives small, so they can grow to fit detail.
+        let scale = (size.min_element() / 8.0).max(2.0);
+        let near = |rng: &mut R| {
+            anchor + Vec2::new(rng.gen_range(-scale..scale), rng.gen_range(-scale..scale))
+        };
+
+        match kind {
+            PrimitiveShape::Triangle => Self::Triangle([anchor, near(rng), near(rng)]),
+            PrimitiveShape::Ellipse => Self::Ellipse {
+                center: anchor,
+                radii: Vec2::new(rng.gen_range(1.0..scale), rng.gen_range(1.0..scale)),
+                rotation: rng.gen_range(0.0..PI),
+            },
+            PrimitiveShape::Rectangle => Self::Rectangle {
+                a: anchor,
+                b: near(rng),
+            },
+            PrimitiveShape::RotatedRectangle => Self::RotatedRectangle {
+                center: anchor,
+                size: Vec2::new(rng.gen_range(1.0..scale), rng.gen_range(1.0..scale)),
+                rotation: rng.gen_range(0.0..PI),
+            },
+            PrimitiveShape::Bezier => Self::Bezier([anchor, near(rng), near(rng)]),
+        }
+    }
+
+    /// Randomly changes a single property of the primitive.
+    fn mutate<R: Rng + ?Sized>(&mut self, size: Vec2, distance: f32, rng: &mut R) {
+        let distance = distance.max(f32::EPSILON);
+
+        let offset = |rng: &mut R| {
+            Vec2::new(
+                rng.gen_range(-distance..distance),
+                rng.gen_range(-distance..distance),
+            )
+        };
+        let nudge = |p: Vec2, rng: &mut R| (p + offset(rng)).clamp(Vec2::ZERO, size);
+        let grow = |v: Vec2, rng: &mut R| (v + offset(rng)).max(Vec2::ONE);
+        let turn = |r: f32, rng: &mut R| r + rng.gen_range(-0.5..0.5);
+
+        match self {
+            Self::Triangle(points) | Self::Bezier(points) => {
+                let i = rng.gen_range(0..points.len());
+                points[i] = nudge(points[i], rng);
+            }
+            Self::Ellipse {
+                center,
+                radii,
+                rotation,
+            } => match rng.gen_range(0..3) {
+                0 => *center = nudge(*center, rng),
+                1 => *radii = grow(*radii, rng),
+                _ => *rotation = turn(*rotation, rng),
+            },
+            Self::Rectangle { a, b } => {
+                if rng.gen() {
+                    *a = nudge(*a, rng);
+                } else {
+                    *b = nudge(*b, rng);
+                }
+            }
+            Self::RotatedRectangle {
+                center,
+                size: rect_size,
+                rotation,
+            } => match rng.gen_range(0..3) {
+                0 => *center = nudge(*center, rng),
+                1 => *rect_size = grow(*rect_size, rng),
+                _ => *rotation = turn(*rotation, rng),
+            },
+        }
+    }
+
+    /// The outline of the primitive, in pixel space.
+    fn polygon(&self, stroke_width: f32) -> Vec<Vec2> {
+        match self {
+            Self::Triangle(points) => points.to_vec(),
+            Self::Ellipse {
+                center,
+                radii,
+                rotation,
+            } => {
+                let rotation = Mat2::from_angle(*rotation);
+
+                (0..Self::CURVE_RESOLUTION)
+                    .map(|i| {
+                        let theta = i as f32 / Self::CURVE_RESOLUTION as f32 * 2.0 * PI;
+
+                        *center + rotation.mul_vec2(Vec2::new(theta.cos(), theta.sin()) * *radii)
+                    })
+                    .collect()
+            }
+            Self::Rectangle { a, b } => {
+                let min = a.min(*b);
+                let max = a.max(*b);
+
+                vec![min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)]
+            }
+            Self::RotatedRectangle {
+                center,
+                size,
+                rotation,
+            } => {
+                let rotation = Mat2::from_angle(*rotation);
+                let half = *size / 2.0;
+
+                [
+                    Vec2::new(-half.x, -half.y),
+                    Vec2::new(half.x, -half.y),
+                    Vec2::new(half.x, half.y),
+                    Vec2::new(-half.x, half.y),
+                ]
+                .iter()
+                .map(|v| *center + rotation.mul_vec2(*v))
+                .collect()
+            }
+            Self::Bezier([start, control, end]) => {
+                let points: Vec<Vec2> = (0..=Self::CURVE_RESOLUTION)
+                    .map(|i| {
+                        let t = i as f32 / Self::CURVE_RESOLUTION as f32;
+
+                        *start * (1.0 - t) * (1.0 - t)
+                            + *control * 2.0 * (1.0 - t) * t
+                            + *end * t * t
+                    })
+                    .collect();
+                let widths = vec![stroke_width; points.len()];
+
+                variable_width_outline(&points, &widths, true)
+            }
+        }
+    }
+}
+
+/// Everything needed to score a [Candidate] against the current approximation.
+struct ScoreContext<'a> {
+    target: &'a RasterCanvas,
+    current: &'a RasterCanvas,
+    alpha: f32,
+    stroke_width: f32,
+}
+
+impl<'a> ScoreContext<'a> {
+    /// Finds the best color for a candidate, and how much drawing it with that color would change the total squared error.
+    /// Lower is better.
+    fn score(&self, candidate: &Candidate) -> (f32, Color) {
+        let spans = polygon_spans(
+            &candidate.polygon(self.stroke_width),
+            self.target.width(),
+            self.target.height(),
+        );
+
+        let alpha = self.alpha.clamp(f32::EPSILON, 1.0);
+
+        // The color that minimizes the error is the one that, once blended, is closest to the target on average.
+        let mut sum = [0.0; 3];
+        let mut count = 0;
+
+        for (y, columns) in &spans {
+            for x in columns.clone() {
+                let target = self.target.get_pixel(x, *y);
+                let current = self.current.get_pixel(x, *y);
+
+                sum[0] += target.r() - current.r() * (1.0 - alpha);
+                sum[1] += target.g() - current.g() * (1.0 - alpha);
+                sum[2] += target.b() - current.b() * (1.0 - alpha);
+                count += 1;
+            }
+        }
+
+        if count == 0 {
+            return (0.0, Color::transparent());
+        }
+
+        let channel = |sum: f32| (sum / (count as f32 * alpha)).clamp(0.0, 1.0);
+        let color = Color::new(channel(sum[0]), channel(sum[1]), channel(sum[2]), alpha);
+
+        let mut delta = 0.0;
+
+        for (y, columns) in &spans {
+            for x in columns.clone() {
+                let target = self.target.get_pixel(x, *y);
+                let current = self.current.get_pixel(x, *y);
+
+                let blend = |over: f32, under: f32| over * alpha + under * (1.0 - alpha);
+                let blended = Color::new(
+                    blend(color.r(), current.r()),
+                    blend(color.g(), current.g()),
+                    blend(color.b(), current.b()),
+                    1.0,
+                );
+
+                delta += pixel_error(target, blended) - pixel_error(target, current);
+            }
+        }
+
+        (delta, color)
+    }
+}
+
+/// The squared error between the color channels of two pixels.
+fn pixel_error(a: Color, b: Color) -> f32 {
+    (a.r() - b.r()).powi(2) + (a.g() - b.g()).powi(2) + (a.b() - b.b()).powi(2)
+}
+
+/// The average color of every pixel in a canvas.
+fn average_color(canvas: &RasterCanvas) -> Color {
+    let count = canvas.get_raw().len().max(1) as f32;
+
+    let (r, g, b) = canvas
+        .get_raw()
+        .iter()
+        .fold((0.0, 0.0, 0.0), |(r, g, b), c| {
+            (r + c.r(), g + c.g(), b + c.b())
+        });
+
+    Color::new(r / count, g / count, b / count, 1.0)
+}
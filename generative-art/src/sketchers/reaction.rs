@@ -0,0 +1,317 @@
+use barium::Stroke;
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::helpers::{contour_lines, PixelTransform};
+use crate::palette::{map_stroke, ColorMap};
+
+/// Where a [ReactionDiffusionSketcher] starts with the second chemical.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReactionSeed {
+    /// A square in the middle of the grid, with sides of a given size (in pixels).
+    Center { size: usize },
+    /// A number of squares at random positions, with sides of a given size (in pixels).
+    Random { count: usize, size: usize },
+    /// Everywhere the input image is darker than a threshold (`0.0` to `1.0`).
+    /// Without an input image, nothing is seeded.
+    Image { threshold: f32 },
+}
+
+/// What a [ReactionDiffusionSketcher] outputs.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ReactionOutput {
+    /// A raster of the concentration of the second chemical, mapped to colors from where there is none (`0.0`) to where there is most (`1.0`).
+    Raster { colors: ColorMap },
+    /// Vector lines where the concentration of the second chemical crosses each level.
+    /// Levels are relative to the highest concentration, from `0.0` to `1.0`.
+    /// If `colors` is set, each line is colored by its level instead of the stroke color.
+    Contours {
+        levels: Vec<f32>,
+        stroke: Stroke,
+        colors: Option<ColorMap>,
+    },
+}
+
+#[derive(Clone)]
+pub struct ReactionDiffusionSketcherSettings {
+    /// The width of the grid. Ignored if there is an input image, which sets the size instead.
+    pub width: usize,
+    /// The height of the grid. Ignored if there is an input image, which sets the size instead.
+    pub height: usize,
+    /// The rate the first chemical is fed in.
+    pub feed: f32,
+    /// The rate the second chemical is removed.
+    pub kill: f32,
+    /// The feed rate where the input image is black. With an input image, the feed rate blends from [feed](Self::feed) in white areas to this.
+    pub dark_feed: f32,
+    /// The kill rate where the input image is black. With an input image, the kill rate blends from [kill](Self::kill) in white areas to this.
+    pub dark_kill: f32,
+    /// How fast the first chemical spreads.
+    pub diffusion_a: f32,
+    /// How fast the second chemical spreads.
+    pub diffusion_b: f32,
+    /// How much time should pass between steps.
+    pub step_length: f32,
+    /// The number of steps to simulate.
+    pub steps: usize,
+    /// Where to start with the second chemical.
+    pub seed: ReactionSeed,
+    /// What to output.
+    pub output: ReactionOutput,
+}
+
+/// Simulates Gray-Scott reaction-diffusion between two chemicals, which forms spots, stripes and mazes.
+pub struct ReactionDiffusionSketcher {
+    settings: ReactionDiffusionSketcherSettings,
+    input_image: Option<RasterCanvas>,
+    width: usize,
+    height: usize,
+    a: Vec<f32>,
+    b: Vec<f32>,
+    feed: Vec<f32>,
+    kill: Vec<f32>,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+impl ReactionDiffusionSketcher {
+    pub fn new(
+        input_image: Option<RasterCanvas>,
+        settings: ReactionDiffusionSketcherSettings,
+        #[cfg(feature = "small-rng")] seed: u64,
+    ) -> Self {
+        let (width, height) = match &input_image {
+            Some(image) => (image.width(), image.height()),
+            None => (settings.width, settings.height),
+        };
+
+        // The rates only vary across the grid with an input image.
+        let darkness: Vec<f32> = match &input_image {
+            Some(image) => (0..width * height)
+                .map(|i| 1.0 - image.get_brightness(i % width, i / width))
+                .collect(),
+            None => vec![0.0; width * height],
+        };
+
+        let feed = darkness
+            .iter()
+            .map(|d| settings.feed + (settings.dark_feed - settings.feed) * d)
+            .collect();
+        let kill = darkness
+            .iter()
+            .map(|d| settings.kill + (settings.dark_kill - settings.kill) * d)
+            .collect();
+
+        let mut sketcher = Self {
+            settings,
+            input_image,
+            width,
+            height,
+            a: vec![1.0; width * height],
+            b: vec![0.0; width * height],
+            feed,
+            kill,
+            #[cfg(feature = "small-rng")]
+            rng: SmallRng::seed_from_u64(seed),
+        };
+
+        sketcher.seed();
+        sketcher
+    }
+
+    /// Consumes the sketcher and returns the input image.
+    pub fn take_input(self) -> Option<RasterCanvas> {
+        self.input_image
+    }
+
+    /// Fills a square of the grid with the second chemical.
+    fn seed_square(&mut self, center_x: usize, center_y: usize, size: usize) {
+        let start_x = center_x.saturating_sub(size / 2);
+        let start_y = center_y.saturating_sub(size / 2);
+
+        for y in start_y..(start_y + size).min(self.height) {
+            for x in start_x..(start_x + size).min(self.width) {
+                self.b[y * self.width + x] = 1.0;
+            }
+        }
+    }
+
+    fn seed(&mut self) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+
+        match self.settings.seed {
+            ReactionSeed::Center { size } => {
+                self.seed_square(self.width / 2, self.height / 2, size)
+            }
+            ReactionSeed::Random { count, size } => {
+                for _ in 0..count {
+                    #[cfg(feature = "thread-rng")]
+                    let mut rng = rand::thread_rng();
+                    #[cfg(feature = "small-rng")]
+                    let rng = &mut self.rng;
+
+                    let x = rng.gen_range(0..self.width);
+                    let y = rng.gen_range(0..self.height);
+
+                    self.seed_square(x, y, size);
+                }
+            }
+            ReactionSeed::Image { threshold } => {
+                if let Some(image) = &self.input_image {
+                    for (i, b) in self.b.iter_mut().enumerate() {
+                        if 1.0 - image.get_brightness(i % self.width, i / self.width) > threshold {
+                            *b = 1.0;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Computes the next step of the simulation.
+    fn step(&mut self) {
+        let width = self.width;
+        let height = self.height;
+
+        let (a, b) = (&self.a, &self.b);
+        let (feed, kill) = (&self.feed, &self.kill);
+        let settings = &self.settings;
+
+        // A 3x3 Laplacian, with the edges of the grid repeated outwards.
+        let laplacian = |grid: &[f32], x: usize, y: usize| {
+            let mut sum = -grid[y * width + x];
+
+            for (dx, dy, weight) in [
+                (-1, 0, 0.2),
+                (1, 0, 0.2),
+                (0, -1, 0.2),
+                (0, 1, 0.2),
+                (-1, -1, 0.05),
+                (1, -1, 0.05),
+                (-1, 1, 0.05),
+                (1, 1, 0.05),
+            ] {
+                let nx = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+                let ny = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+
+                sum += grid[ny * width + nx] * weight;
+            }
+
+            sum
+        };
+
+        let update = |(y, (row_a, row_b)): (usize, (&mut [f32], &mut [f32]))| {
+            for x in 0..width {
+                let i = y * width + x;
+                let reaction = a[i] * b[i] * b[i];
+
+                row_a[x] = a[i]
+                    + (settings.diffusion_a * laplacian(a, x, y) - reaction
+                        + feed[i] * (1.0 - a[i]))
+                        * settings.step_length;
+                row_b[x] = b[i]
+                    + (settings.diffusion_b * laplacian(b, x, y) + reaction
+                        - (kill[i] + feed[i]) * b[i])
+                        * settings.step_length;
+            }
+        };
+
+        let mut next_a = vec![0.0; width * height];
+        let mut next_b = vec![0.0; width * height];
+
+        #[cfg(feature = "parallel")]
+        next_a
+            .par_chunks_mut(width)
+            .zip(next_b.par_chunks_mut(width))
+            .enumerate()
+            .for_each(update);
+        #[cfg(not(feature = "parallel"))]
+        next_a
+            .chunks_mut(width)
+            .zip(next_b.chunks_mut(width))
+            .enumerate()
+            .for_each(update);
+
+        self.a = next_a;
+        self.b = next_b;
+    }
+
+    /// Renders the concentration of the second chemical.
+    fn render(&self) -> OmniCanvas {
+        let max = self.b.iter().copied().fold(f32::EPSILON, f32::max);
+
+        match &self.settings.output {
+            ReactionOutput::Raster { colors } => {
+                let mut canvas = RasterCanvas::new(self.width, self.height);
+
+                for (i, b) in self.b.iter().enumerate() {
+                    canvas.set_pixel(
+                        i % self.width,
+                        i / self.width,
+                        colors.sample((b / max).clamp(0.0, 1.0)),
+                    );
+                }
+
+                canvas.into()
+            }
+            ReactionOutput::Contours {
+                levels,
+                stroke,
+                colors,
+            } => {
+                let transform = PixelTransform::new(self.width, self.height);
+                let mut canvas = VectorCanvas::default();
+
+                for level in levels {
+                    let stroke = map_stroke(*stroke, colors.as_ref(), *level);
+
+                    for line in contour_lines(&self.b, self.width, self.height, level * max) {
+                        canvas.draw_shape(
+                            line.into_iter().map(|p| transform.apply(p)).collect(),
+                            Some(stroke),
+                            None,
+                        );
+                    }
+                }
+
+                canvas.into()
+            }
+        }
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+
+        for i in 0..self.settings.steps {
+            before_iter(i as f32 / self.settings.steps as f32);
+
+            self.step();
+        }
+    }
+}
+
+impl<P> Sketcher<P> for ReactionDiffusionSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.render()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.render()
+    }
+}
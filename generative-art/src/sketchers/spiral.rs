@@ -0,0 +1,301 @@
+use std::f32::consts::PI;
+
+use barium::{LineEnd, Stroke, Vec2};
+
+use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::helpers::{variable_width_outline, PixelTransform};
+use crate::palette::{map_stroke, ColorMap};
+
+use super::Sketcher;
+
+/// The shape of the path(s) a [SpiralSketcher] draws along.
+#[derive(Clone, Copy)]
+pub enum SpiralMode {
+    /// A single Archimedean spiral, winding outwards from the center.
+    Spiral,
+    /// Concentric circles around the center.
+    Concentric,
+    /// Straight rays leaving the center.
+    Radial { rays: usize },
+}
+
+/// How the brightness of the input image changes the path.
+#[derive(Clone, Copy)]
+pub enum SpiralModulation {
+    /// Squiggle perpendicular to the path. Amplitude is in pixels and frequency is in radians per pixel.
+    Amplitude { amplitude: f32, frequency: f32 },
+    /// Scale the width of the stroke. Each line is drawn as a single filled outline.
+    Width,
+    /// Break the path into dashes, which are longer in more intense areas.
+    /// `dash_length` is the length of a dash plus the gap after it, in pixels,
+    /// and the visible dash is `intensity * dash_length` long.
+    Gaps { dash_length: f32 },
+}
+
+#[derive(Clone)]
+pub struct SpiralSketcherSettings {
+    /// What stroke to create the lines with.
+    pub stroke: Stroke,
+    /// If set, colors each line by how far along the path(s) it is, from the first sample (`0.0`) to the last (`1.0`),
+    /// instead of the stroke color.
+    pub stroke_colors: Option<ColorMap>,
+    /// The shape of the path(s).
+    pub mode: SpiralMode,
+    /// How the input image modulates the path(s).
+    pub modulation: SpiralModulation,
+    /// The center of the spiral, relative to the size of the image. `(0.5, 0.5)` is the center of the image.
+    pub center: Vec2,
+    /// The distance between turns of the spiral or between concentric circles, in pixels.
+    pub spacing: f32,
+    /// The distance between samples along the path, in pixels.
+    pub sample_distance: f32,
+    /// By default, dark areas are more intense. This makes light areas more intense instead.
+    pub invert_brightness: bool,
+    /// Set an intensity threshold under which, no lines are drawn. This is effected by [invert_brightness](Self::invert_brightness).
+    pub intensity_threshold: f32,
+    /// Radius of a box blur applied to the input image. For no box blur, set this to zero.
+    pub box_blur_radius: usize,
+}
+
+/// Draws a spiral, concentric circles or rays across an image, modulated by the brightness underneath.
+pub struct SpiralSketcher {
+    settings: SpiralSketcherSettings,
+    transform: PixelTransform,
+    input_image: RasterCanvas,
+    canvas: VectorCanvas,
+}
+
+impl SpiralSketcher {
+    pub fn new(input_image: RasterCanvas, settings: SpiralSketcherSettings) -> Self {
+        Self {
+            settings,
+            transform: PixelTransform::new(input_image.width(), input_image.height()),
+            input_image,
+            canvas: VectorCanvas::default(),
+        }
+    }
+
+    /// Gets the intensity (0..1) of the input image at a point in pixel space.
+    /// Returns [None] if the point is outside of the image.
+    fn intensity(&self, p: Vec2) -> Option<f32> {
+        if p.x < 0.0
+            || p.y < 0.0
+            || p.x >= self.input_image.width() as f32
+            || p.y >= self.input_image.height() as f32
+        {
+            return None;
+        }
+
+        let brightness = self.input_image.get_blurred_brightness(
+            p.x as usize,
+            p.y as usize,
+            self.settings.box_blur_radius,
+        );
+
+        if self.settings.invert_brightness {
+            Some(brightness)
+        } else {
+            Some(1.0 - brightness)
+        }
+    }
+
+    /// Generates the tracks to draw along, as lists of points and their normals, in pixel space.
+    fn tracks(&self) -> Vec<Vec<(Vec2, Vec2)>> {
+        let size = Vec2::new(
+            self.input_image.width() as f32,
+            self.input_image.height() as f32,
+        );
+        let center = self.settings.center * size;
+
+        // Distance from the center to the furthest corner, so that the entire image is covered.
+        let max_radius = [
+            Vec2::ZERO,
+            Vec2::new(size.x, 0.0),
+            Vec2::new(0.0, size.y),
+            size,
+        ]
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0, f32::max);
+
+        let spacing = self.settings.spacing.max(f32::EPSILON);
+        let sample_distance = self.settings.sample_distance.max(f32::EPSILON);
+
+        match self.settings.mode {
+            SpiralMode::Spiral => {
+                let mut track = Vec::new();
+                let mut theta: f32 = 0.0;
+
+                loop {
+                    let radius = spacing * theta / (2.0 * PI);
+
+                    if radius > max_radius {
+                        break;
+                    }
+
+                    let normal = Vec2::new(theta.cos(), theta.sin());
+                    track.push((center + normal * radius, normal));
+
+                    theta += sample_distance / radius.max(sample_distance);
+                }
+
+                vec![track]
+            }
+            SpiralMode::Concentric => {
+                let rings = (max_radius / spacing).ceil() as usize;
+
+                (1..=rings)
+                    .map(|ring| {
+                        let radius = ring as f32 * spacing;
+                        let samples =
+                            (2.0 * PI * radius / sample_distance).ceil().max(3.0) as usize;
+
+                        (0..=samples)
+                            .map(|i| {
+                                let theta = i as f32 / samples as f32 * 2.0 * PI;
+                                let normal = Vec2::new(theta.cos(), theta.sin());
+
+                                (center + normal * radius, normal)
+                            })
+                            .collect()
+                    })
+                    .collect()
+            }
+            SpiralMode::Radial { rays } => {
+                let samples = (max_radius / sample_distance).ceil() as usize;
+
+                (0..rays)
+                    .map(|ray| {
+                        let theta = ray as f32 / rays as f32 * 2.0 * PI;
+                        let direction = Vec2::new(theta.cos(), theta.sin());
+                        let normal = direction.perp();
+
+                        (0..=samples)
+                            .map(|i| (center + direction * i as f32 * sample_distance, normal))
+                            .collect()
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Draws the current line, if it is long enough, then starts a new one.
+    /// With [SpiralModulation::Width], the line is drawn as a single filled outline.
+    fn flush_line(&mut self, line_points: &mut Vec<Vec2>, line_widths: &mut Vec<f32>, t: f32) {
+        let stroke = map_stroke(
+            self.settings.stroke,
+            self.settings.stroke_colors.as_ref(),
+            t,
+        );
+
+        if line_points.len() >= 2 {
+            if let SpiralModulation::Width = self.settings.modulation {
+                let outline = variable_width_outline(
+                    line_points,
+                    line_widths,
+                    stroke.line_end == LineEnd::Round,
+                );
+
+                if !outline.is_empty() {
+                    self.canvas.draw_shape(outline, None, Some(stroke.color));
+                }
+
+                line_points.clear();
+            } else {
+                self.canvas
+                    .draw_shape(std::mem::take(line_points), Some(stroke), None);
+            }
+        } else {
+            line_points.clear();
+        }
+
+        line_widths.clear();
+    }
+
+    /// Draws along a single track, which starts a number of samples into the path(s).
+    fn draw_track(&mut self, track: &[(Vec2, Vec2)], start: usize, total_samples: usize) {
+        let sample_distance = self.settings.sample_distance;
+
+        let mut a = 0.0;
+        let mut line_points = Vec::new();
+        let mut line_widths = Vec::new();
+
+        for (i, &(p, normal)) in track.iter().enumerate() {
+            let t = (start + i) as f32 / total_samples.max(1) as f32;
+
+            let intensity = match self.intensity(p) {
+                Some(intensity) if intensity >= self.settings.intensity_threshold => intensity,
+                _ => {
+                    self.flush_line(&mut line_points, &mut line_widths, t);
+                    continue;
+                }
+            };
+
+            match self.settings.modulation {
+                SpiralModulation::Amplitude {
+                    amplitude,
+                    frequency,
+                } => {
+                    a += intensity * frequency * sample_distance;
+                    a %= 2.0 * PI;
+
+                    let offset = normal * a.sin() * amplitude * intensity;
+                    line_points.push(self.transform.apply(p + offset));
+                }
+                SpiralModulation::Width => {
+                    line_points.push(self.transform.apply(p));
+                    line_widths.push(self.settings.stroke.width * intensity);
+                }
+                SpiralModulation::Gaps { dash_length } => {
+                    let dash_length = dash_length.max(f32::EPSILON);
+
+                    a += sample_distance;
+                    a %= dash_length;
+
+                    if a <= intensity * dash_length {
+                        line_points.push(self.transform.apply(p));
+                    } else {
+                        self.flush_line(&mut line_points, &mut line_widths, t);
+                    }
+                }
+            }
+        }
+
+        self.flush_line(
+            &mut line_points,
+            &mut line_widths,
+            (start + track.len()) as f32 / total_samples.max(1) as f32,
+        );
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        let tracks = self.tracks();
+        let total_samples: usize = tracks.iter().map(|track| track.len()).sum();
+
+        let mut drawn_samples = 0;
+        for track in &tracks {
+            before_iter(drawn_samples as f32 / total_samples as f32);
+
+            self.draw_track(track, drawn_samples, total_samples);
+
+            drawn_samples += track.len();
+        }
+    }
+}
+
+impl<P> Sketcher<P> for SpiralSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
@@ -0,0 +1,344 @@
+use barium::{Color, Stroke, Vec2};
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::helpers::{inset_convex_polygon, polygon_spans, PixelTransform};
+use crate::palette::{map_stroke, ColorMap};
+
+/// How a [SubdivisionSketcher] splits regions.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SubdivisionShape {
+    /// Splits rectangles in two, across a random axis (favoring the longer one), for Mondrian-like compositions.
+    Rectangles,
+    /// Splits rectangles in four, like a quadtree.
+    Quadtree,
+    /// Splits triangles in two, from their longest edge to the opposite corner.
+    Triangles,
+}
+
+/// How a [SubdivisionSketcher] fills the regions it ends up with.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SubdivisionFill {
+    /// A random color from a palette. Colors can be repeated to make them more likely.
+    Palette(Vec<Color>),
+    /// A color picked by the depth of the region, from the first split (`0.0`) to the [max_depth](SubdivisionSketcherSettings::max_depth) (`1.0`).
+    Depth(ColorMap),
+    /// The average color of the input image under the region. Without an input image, regions are left unfilled.
+    Image,
+    /// No fill, for outlines only.
+    None,
+}
+
+#[derive(Clone)]
+pub struct SubdivisionSketcherSettings {
+    /// How to split regions.
+    pub shape: SubdivisionShape,
+    /// Regions are always split until they are this deep.
+    pub min_depth: usize,
+    /// Regions are never split beyond this depth.
+    pub max_depth: usize,
+    /// The chance that a region between the minimum and maximum depth is split.
+    /// Ignored when there is an input image, in which case [variance_threshold](Self::variance_threshold) decides.
+    pub split_chance: f32,
+    /// How far from the middle splits may be. At `0.0` regions are split in half, and at `1.0` anywhere along their side.
+    pub split_variation: f32,
+    /// Regions whose longest side is shorter than twice this are never split, in canvas space.
+    pub min_size: f32,
+    /// With an input image, regions are split only if the variance of the brightness underneath is above this.
+    /// Lower values split detailed areas of the image more.
+    pub variance_threshold: f32,
+    /// How to fill each region.
+    pub fill: SubdivisionFill,
+    /// Space left between neighbouring regions, in canvas space.
+    pub gap: f32,
+    /// If set, the outline of each region is drawn with this stroke.
+    pub outline: Option<Stroke>,
+    /// If set, colors the outline of each region by its depth, like [SubdivisionFill::Depth], instead of the outline color.
+    pub outline_colors: Option<ColorMap>,
+}
+
+/// Recursively splits the canvas into rectangles or triangles, optionally guided by the detail of an input image.
+pub struct SubdivisionSketcher {
+    settings: SubdivisionSketcherSettings,
+    input_image: Option<RasterCanvas>,
+    canvas: VectorCanvas,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+impl SubdivisionSketcher {
+    /// Creates a new sketcher. With an input image, the canvas matches its aspect ratio,
+    /// and without one, the canvas is the square -1..1.
+    pub fn new(
+        input_image: Option<RasterCanvas>,
+        settings: SubdivisionSketcherSettings,
+        #[cfg(feature = "small-rng")] seed: u64,
+    ) -> Self {
+        Self {
+            settings,
+            input_image,
+            canvas: VectorCanvas::default(),
+            #[cfg(feature = "small-rng")]
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Consumes the sketcher and returns the input image.
+    pub fn take_input(self) -> Option<RasterCanvas> {
+        self.input_image
+    }
+
+    /// The rectangle covered by the canvas, in canvas space.
+    fn bounds(&self) -> (Vec2, Vec2) {
+        match &self.input_image {
+            Some(image) => {
+                let transform = PixelTransform::new(image.width(), image.height());
+
+                let a = transform.apply(Vec2::ZERO);
+                let b = transform.apply(Vec2::new(image.width() as f32, image.height() as f32));
+
+                (a.min(b), a.max(b))
+            }
+            None => (Vec2::splat(-1.0), Vec2::ONE),
+        }
+    }
+
+    /// Converts a region from canvas space to the pixel space of an image.
+    fn to_pixels(image: &RasterCanvas, region: &[Vec2]) -> Vec<Vec2> {
+        let transform = PixelTransform::new(image.width(), image.height());
+
+        region.iter().map(|p| transform.invert(*p)).collect()
+    }
+
+    /// The variance of the brightness of the input image under a region, if there is an input image.
+    fn variance(&self, region: &[Vec2]) -> Option<f32> {
+        let image = self.input_image.as_ref()?;
+
+        let mut sum = 0.0;
+        let mut sum_squared = 0.0;
+        let mut count = 0;
+
+        for (y, columns) in polygon_spans(
+            &Self::to_pixels(image, region),
+            image.width(),
+            image.height(),
+        ) {
+            for x in columns {
+                let brightness = image.get_brightness(x, y);
+
+                sum += brightness;
+                sum_squared += brightness * brightness;
+                count += 1;
+            }
+        }
+
+        if count == 0 {
+            return Some(0.0);
+        }
+
+        let mean = sum / count as f32;
+
+        Some(sum_squared / count as f32 - mean * mean)
+    }
+
+    fn should_split(&mut self, region: &[Vec2], depth: usize) -> bool {
+        let longest_side = (0..region.len())
+            .map(|i| region[i].distance(region[(i + 1) % region.len()]))
+            .fold(0.0, f32::max);
+
+        if depth >= self.settings.max_depth || longest_side < 2.0 * self.settings.min_size {
+            return false;
+        }
+
+        if depth < self.settings.min_depth {
+            return true;
+        }
+
+        if let Some(variance) = self.variance(region) {
+            return variance > self.settings.variance_threshold;
+        }
+
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let rng = &mut self.rng;
+
+        rng.gen::<f32>() < self.settings.split_chance
+    }
+
+    /// Splits a region into smaller ones.
+    fn split(&mut self, region: &[Vec2]) -> Vec<Vec<Vec2>> {
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let rng = &mut self.rng;
+
+        let variation = self.settings.split_variation.clamp(0.0, 1.0);
+        let mut split_point = || 0.5 + (rng.gen::<f32>() - 0.5) * variation;
+
+        let rectangle =
+            |min: Vec2, max: Vec2| vec![min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+
+        match self.settings.shape {
+            SubdivisionShape::Rectangles | SubdivisionShape::Quadtree => {
+                let min = region
+                    .iter()
+                    .fold(Vec2::splat(f32::INFINITY), |a, &b| a.min(b));
+                let max = region
+                    .iter()
+                    .fold(Vec2::splat(f32::NEG_INFINITY), |a, &b| a.max(b));
+                let size = max - min;
+
+                let split = min + size * Vec2::new(split_point(), split_point());
+
+                if self.settings.shape == SubdivisionShape::Quadtree {
+                    vec![
+                        rectangle(min, split),
+                        rectangle(Vec2::new(split.x, min.y), Vec2::new(max.x, split.y)),
+                        rectangle(split, max),
+                        rectangle(Vec2::new(min.x, split.y), Vec2::new(split.x, max.y)),
+                    ]
+                } else if rng.gen::<f32>() * (size.x + size.y) < size.x {
+                    vec![
+                        rectangle(min, Vec2::new(split.x, max.y)),
+                        rectangle(Vec2::new(split.x, min.y), max),
+                    ]
+                } else {
+                    vec![
+                        rectangle(min, Vec2::new(max.x, split.y)),
+                        rectangle(Vec2::new(min.x, split.y), max),
+                    ]
+                }
+            }
+            SubdivisionShape::Triangles => {
+                let longest = (0..3)
+                    .max_by(|&i, &j| {
+                        let length = |k: usize| region[k].distance_squared(region[(k + 1) % 3]);
+
+                        length(i)
+                            .partial_cmp(&length(j))
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                    .unwrap_or(0);
+
+                let a = region[longest];
+                let b = region[(longest + 1) % 3];
+                let c = region[(longest + 2) % 3];
+                let m = a + (b - a) * split_point();
+
+                vec![vec![a, m, c], vec![m, b, c]]
+            }
+        }
+    }
+
+    /// The position of a depth along a color map.
+    fn depth_position(&self, depth: usize) -> f32 {
+        depth as f32 / self.settings.max_depth.max(1) as f32
+    }
+
+    /// Picks the fill color of a region.
+    fn fill(&mut self, region: &[Vec2], depth: usize) -> Option<Color> {
+        let position = self.depth_position(depth);
+
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let rng = &mut self.rng;
+
+        match &self.settings.fill {
+            SubdivisionFill::Palette(colors) if !colors.is_empty() => {
+                Some(colors[rng.gen_range(0..colors.len())])
+            }
+            SubdivisionFill::Depth(colors) => Some(colors.sample(position)),
+            SubdivisionFill::Image => self
+                .input_image
+                .as_ref()
+                .and_then(|image| image.get_average_color(&Self::to_pixels(image, region))),
+            _ => None,
+        }
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        let (min, max) = self.bounds();
+
+        let mut regions = match self.settings.shape {
+            SubdivisionShape::Rectangles | SubdivisionShape::Quadtree => {
+                vec![vec![
+                    min,
+                    Vec2::new(max.x, min.y),
+                    max,
+                    Vec2::new(min.x, max.y),
+                ]]
+            }
+            SubdivisionShape::Triangles => vec![
+                vec![min, Vec2::new(max.x, min.y), max],
+                vec![min, max, Vec2::new(min.x, max.y)],
+            ],
+        };
+        let mut leaves = Vec::new();
+
+        // Regions are split one level at a time, so progress is reported per level.
+        for depth in 0..=self.settings.max_depth {
+            before_iter(depth as f32 / (self.settings.max_depth + 1) as f32);
+
+            let mut next = Vec::new();
+
+            for region in regions {
+                if self.should_split(&region, depth) {
+                    next.extend(self.split(&region));
+                } else {
+                    leaves.push((region, depth));
+                }
+            }
+
+            regions = next;
+
+            if regions.is_empty() {
+                break;
+            }
+        }
+
+        for (leaf, depth) in leaves {
+            let fill = self.fill(&leaf, depth);
+
+            let polygon = if self.settings.gap > 0.0 {
+                match inset_convex_polygon(&leaf, self.settings.gap / 2.0) {
+                    Some(polygon) => polygon,
+                    None => continue,
+                }
+            } else {
+                leaf
+            };
+
+            let outline = self.settings.outline.map(|stroke| {
+                map_stroke(
+                    stroke,
+                    self.settings.outline_colors.as_ref(),
+                    self.depth_position(depth),
+                )
+            });
+
+            self.canvas.draw_shape(polygon, outline, fill);
+        }
+    }
+}
+
+impl<P> Sketcher<P> for SubdivisionSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use barium::{Color, Stroke, Vec2};
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
+
+use super::Sketcher;
+use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
+use crate::helpers::{value_noise, PixelTransform};
+use crate::palette::{map_stroke, ColorMap};
+
+/// A tile a [TileSketcher] can place in a cell.
+#[derive(Clone)]
+pub struct Tile {
+    /// The shapes of the tile, drawn in the space -1..1 (with y pointing up), which is scaled to fill the cell.
+    pub shape: VectorCanvas,
+    /// Labels for the edges of the tile, counter-clockwise from the right: right, top, left and bottom.
+    /// With [edge_matching](TileSketcherSettings::edge_matching), neighbouring tiles only meet along equal labels.
+    pub edges: [u32; 4],
+    /// The relative chance of picking this tile, for [TileSelection::Random].
+    pub weight: f32,
+}
+
+impl Tile {
+    /// Creates a tile from shapes, with no edge constraints.
+    pub fn new(shape: VectorCanvas) -> Self {
+        Self {
+            shape,
+            edges: [0; 4],
+            weight: 1.0,
+        }
+    }
+
+    /// The classic Truchet tile: a square split along its diagonal, with one half filled.
+    /// The filled edges are labelled `1`, so edge matching joins the filled halves into larger shapes.
+    pub fn truchet(color: Color) -> Self {
+        let mut shape = VectorCanvas::default();
+        shape.draw_shape(
+            vec![
+                Vec2::new(-1.0, -1.0),
+                Vec2::new(1.0, -1.0),
+                Vec2::new(-1.0, 1.0),
+            ],
+            None,
+            Some(color),
+        );
+
+        Self {
+            edges: [0, 0, 1, 1],
+            ..Self::new(shape)
+        }
+    }
+
+    /// A line from one corner to the opposite one. Rotated copies make the "10 PRINT" maze.
+    /// The stroke width is relative to the tile, which is two units across.
+    pub fn diagonal(stroke: Stroke) -> Self {
+        let mut shape = VectorCanvas::default();
+        shape.draw_shape(
+            vec![Vec2::new(-1.0, -1.0), Vec2::new(1.0, 1.0)],
+            Some(stroke),
+            None,
+        );
+
+        Self::new(shape)
+    }
+
+    /// The Smith tile: two quarter circles, each joining the middles of two adjacent edges.
+    /// The stroke width is relative to the tile, which is two units across.
+    pub fn smith(stroke: Stroke) -> Self {
+        const SEGMENTS: usize = 12;
+
+        let mut shape = VectorCanvas::default();
+
+        for (corner, start) in [(Vec2::new(-1.0, -1.0), 0.0), (Vec2::new(1.0, 1.0), PI)] {
+            shape.draw_shape(
+                (0..=SEGMENTS)
+                    .map(|i| {
+                        let angle = start + i as f32 / SEGMENTS as f32 * PI / 2.0;
+
+                        corner + Vec2::new(angle.cos(), angle.sin())
+                    })
+                    .collect(),
+                Some(stroke),
+                None,
+            );
+        }
+
+        Self::new(shape)
+    }
+}
+
+/// How a [TileSketcher] picks the tile for each cell.
+///
+/// Each tile (and, with [rotations](TileSketcherSettings::rotations), each of its rotations) is a separate option,
+/// in the order they are given.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TileSelection {
+    /// At random, in proportion to the weights of the tiles.
+    Random,
+    /// By smooth noise, so that nearby cells tend to pick similar options. The scale is the size of the noise features, in canvas space.
+    Noise { scale: f32 },
+    /// By the brightness of the input image under each cell, picking earlier options for lighter cells.
+    /// Without an input image, tiles are picked at random.
+    Brightness { invert: bool },
+}
+
+#[derive(Clone)]
+pub struct TileSketcherSettings {
+    /// The tiles to pick from.
+    pub tiles: Vec<Tile>,
+    /// The size of the largest tiles, in canvas space. Nothing is drawn unless it is positive.
+    pub tile_size: f32,
+    /// Whether tiles may also be placed rotated by 90, 180 and 270 degrees.
+    pub rotations: bool,
+    /// How to pick the tile for each cell.
+    pub selection: TileSelection,
+    /// The number of tile sizes. Each size is half as large as the previous one.
+    pub scales: usize,
+    /// The chance that a cell is split into four smaller cells, if there are smaller sizes left.
+    pub subdivision_chance: f32,
+    /// Only place tiles whose edges match those of their neighbours (like Wang tiles).
+    /// Tiles are placed from the bottom left, so each is matched against the tiles to its left and below it.
+    /// Only neighbours of the same size are matched. If no tile matches, any tile may be placed.
+    pub edge_matching: bool,
+    /// If set, colors the strokes of each tile by its cell, instead of the colors of the tile. See [fill_colors](Self::fill_colors).
+    pub stroke_colors: Option<ColorMap>,
+    /// If set, colors the fills of each tile by its cell, instead of the colors of the tile.
+    /// Cells are mapped by the brightness of the input image under them, from dark (`0.0`) to light (`1.0`),
+    /// or without an input image, by their position from the bottom left (`0.0`) to the top right (`1.0`) of the canvas.
+    pub fill_colors: Option<ColorMap>,
+}
+
+/// Fills a grid with tiles, like Truchet tiles, Smith tiles or Wang tiles.
+pub struct TileSketcher {
+    settings: TileSketcherSettings,
+    input_image: Option<RasterCanvas>,
+    canvas: VectorCanvas,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
+}
+
+impl TileSketcher {
+    /// Creates a new sketcher. With an input image, the canvas matches its aspect ratio,
+    /// and without one, the canvas is the square -1..1.
+    pub fn new(
+        input_image: Option<RasterCanvas>,
+        settings: TileSketcherSettings,
+        #[cfg(feature = "small-rng")] seed: u64,
+    ) -> Self {
+        Self {
+            settings,
+            input_image,
+            canvas: VectorCanvas::default(),
+            #[cfg(feature = "small-rng")]
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Consumes the sketcher and returns the input image.
+    pub fn take_input(self) -> Option<RasterCanvas> {
+        self.input_image
+    }
+
+    fn random(&mut self) -> f32 {
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let rng = &mut self.rng;
+
+        rng.gen()
+    }
+
+    /// The rectangle covered by the canvas, in canvas space.
+    fn bounds(&self) -> (Vec2, Vec2) {
+        match &self.input_image {
+            Some(image) => {
+                let transform = PixelTransform::new(image.width(), image.height());
+
+                let a = transform.apply(Vec2::ZERO);
+                let b = transform.apply(Vec2::new(image.width() as f32, image.height() as f32));
+
+                (a.min(b), a.max(b))
+            }
+            None => (Vec2::splat(-1.0), Vec2::ONE),
+        }
+    }
+
+    /// The darkness of the input image under a cell, accounting for inversion.
+    fn darkness(&self, image: &RasterCanvas, min: Vec2, max: Vec2, invert: bool) -> f32 {
+        let transform = PixelTransform::new(image.width(), image.height());
+
+        let corners: Vec<Vec2> = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)]
+            .iter()
+            .map(|p| transform.invert(*p))
+            .collect();
+
+        // Cells smaller than a pixel may not cover any pixel centers, so fall back to the nearest pixel.
+        // An empty image has no pixels at all, so it is treated as transparent.
+        let color = image.get_average_color(&corners).unwrap_or_else(|| {
+            if image.width() == 0 || image.height() == 0 {
+                return Color::new(0.0, 0.0, 0.0, 0.0);
+            }
+
+            let center = transform.invert((min + max) / 2.0);
+
+            image.get_pixel(
+                (center.x.max(0.0) as usize).min(image.width() - 1),
+                (center.y.max(0.0) as usize).min(image.height() - 1),
+            )
+        });
+
+        let brightness = (color.r() + color.g() + color.b()) * color.a() / 3.0;
+
+        if invert {
+            brightness
+        } else {
+            1.0 - brightness
+        }
+    }
+
+    /// The value a cell is mapped to colors by. See [fill_colors](TileSketcherSettings::fill_colors).
+    fn color_value(&self, min: Vec2, max: Vec2) -> f32 {
+        match &self.input_image {
+            // Inverted darkness is brightness.
+            Some(image) => self.darkness(image, min, max, true),
+            None => {
+                let (canvas_min, canvas_max) = self.bounds();
+                let p = ((min + max) / 2.0 - canvas_min) / (canvas_max - canvas_min);
+
+                ((p.x + p.y) / 2.0).clamp(0.0, 1.0)
+            }
+        }
+    }
+
+    /// The edges of a tile after rotating it counter-clockwise a number of quarter turns.
+    fn rotated_edges(&self, (tile, rotation): (usize, usize)) -> [u32; 4] {
+        let edges = self.settings.tiles[tile].edges;
+
+        [0, 1, 2, 3].map(|i| edges[(i + 4 - rotation) % 4])
+    }
+
+    /// Places a tile (or smaller tiles) in a cell of the grid for a given level of subdivision.
+    fn place(
+        &mut self,
+        cell: (usize, i64, i64),
+        origin: Vec2,
+        tile_size: f32,
+        options: &[(usize, usize)],
+        placed: &mut HashMap<(usize, i64, i64), (usize, usize)>,
+        noise_seed: u32,
+    ) {
+        let (level, x, y) = cell;
+
+        let size = tile_size / (1 << level) as f32;
+        let min = origin + Vec2::new(x as f32, y as f32) * size;
+        let max = min + Vec2::splat(size);
+
+        if level + 1 < self.settings.scales && self.random() < self.settings.subdivision_chance {
+            // Visit the smaller cells from the bottom left, so their left and lower neighbours are placed first.
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                self.place(
+                    (level + 1, x * 2 + dx, y * 2 + dy),
+                    origin,
+                    tile_size,
+                    options,
+                    placed,
+                    noise_seed,
+                );
+            }
+
+            return;
+        }
+
+        let mut allowed: Vec<(usize, usize)> = options.to_vec();
+
+        if self.settings.edge_matching {
+            let left = placed
+                .get(&(level, x - 1, y))
+                .map(|&o| self.rotated_edges(o));
+            let below = placed
+                .get(&(level, x, y - 1))
+                .map(|&o| self.rotated_edges(o));
+
+            let matching: Vec<(usize, usize)> = options
+                .iter()
+                .copied()
+                .filter(|&option| {
+                    let edges = self.rotated_edges(option);
+
+                    let matches_left = left.map(|left| left[0] == edges[2]).unwrap_or(true);
+                    let matches_below = below.map(|below| below[1] == edges[3]).unwrap_or(true);
+
+                    matches_left && matches_below
+                })
+                .collect();
+
+            if !matching.is_empty() {
+                allowed = matching;
+            }
+        }
+
+        let value = match self.settings.selection {
+            TileSelection::Noise { scale } => Some(value_noise(
+                (min + max) / 2.0 / scale.max(f32::EPSILON),
+                noise_seed,
+            )),
+            TileSelection::Brightness { invert } => self
+                .input_image
+                .as_ref()
+                .map(|image| self.darkness(image, min, max, invert)),
+            TileSelection::Random => None,
+        };
+
+        let option = match value {
+            Some(value) => {
+                let index = (value.clamp(0.0, 1.0) * allowed.len() as f32) as usize;
+
+                allowed[index.min(allowed.len() - 1)]
+            }
+            None => {
+                let total: f32 = allowed
+                    .iter()
+                    .map(|&(tile, _)| self.settings.tiles[tile].weight)
+                    .sum();
+                let mut choice = self.random() * total;
+
+                allowed
+                    .iter()
+                    .copied()
+                    .find(|&(tile, _)| {
+                        choice -= self.settings.tiles[tile].weight;
+                        choice < 0.0
+                    })
+                    .unwrap_or(allowed[allowed.len() - 1])
+            }
+        };
+
+        placed.insert(cell, option);
+
+        let (tile, rotation) = option;
+        let center = (min + max) / 2.0;
+        let scale = size / 2.0;
+
+        let color_value =
+            if self.settings.stroke_colors.is_some() || self.settings.fill_colors.is_some() {
+                self.color_value(min, max)
+            } else {
+                0.0
+            };
+
+        let place_point = |p: Vec2| {
+            let p = (0..rotation).fold(p, |p, _| p.perp());
+
+            center + p * scale
+        };
+
+        for shape in self.settings.tiles[tile].shape.as_raw() {
+            self.canvas.draw_shape(
+                shape.points.iter().map(|p| place_point(*p)).collect(),
+                shape.stroke.map(|stroke| Stroke {
+                    width: stroke.width * scale,
+                    ..map_stroke(stroke, self.settings.stroke_colors.as_ref(), color_value)
+                }),
+                shape.fill.map(|fill| match &self.settings.fill_colors {
+                    Some(colors) => colors.sample(color_value),
+                    None => fill,
+                }),
+            );
+        }
+    }
+
+    fn run<P: Fn(f32)>(&mut self, before_iter: P) {
+        let tile_size = self.settings.tile_size;
+
+        // Without a positive size the grid would need endless cells.
+        if self.settings.tiles.is_empty() || tile_size.is_nan() || tile_size <= 0.0 {
+            return;
+        }
+
+        let (min, max) = self.bounds();
+
+        let columns = ((max.x - min.x) / tile_size).ceil() as i64;
+        let rows = ((max.y - min.y) / tile_size).ceil() as i64;
+
+        let rotations = if self.settings.rotations { 4 } else { 1 };
+        let options: Vec<(usize, usize)> = (0..self.settings.tiles.len())
+            .flat_map(|tile| (0..rotations).map(move |rotation| (tile, rotation)))
+            .collect();
+
+        let noise_seed = (self.random() * u32::MAX as f32) as u32;
+        let mut placed = HashMap::new();
+
+        for y in 0..rows {
+            before_iter(y as f32 / rows as f32);
+
+            for x in 0..columns {
+                self.place((0, x, y), min, tile_size, &options, &mut placed, noise_seed);
+            }
+        }
+    }
+}
+
+impl<P> Sketcher<P> for TileSketcher
+where
+    P: Fn(f32),
+{
+    fn run(&mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.clone().into()
+    }
+
+    fn run_and_dispose(mut self, before_iter: P) -> OmniCanvas {
+        self.run(before_iter);
+
+        self.canvas.into()
+    }
+}
@@ -1,14 +1,59 @@
 use std::f32::consts::PI;
 
 use crate::canvas::{OmniCanvas, RasterCanvas, VectorCanvas};
-use barium::{Stroke, Vec2, Mat2};
+use crate::helpers::{variable_width_outline, PixelTransform};
+use crate::palette::{map_stroke, ColorMap};
+use barium::{LineEnd, Stroke, Vec2};
+use rand::Rng;
+#[cfg(feature = "small-rng")]
+use rand::{rngs::SmallRng, SeedableRng};
 
 use super::Sketcher;
 
+/// The shape of a single period of a wave.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    Square,
+    Sawtooth,
+}
+
+impl Waveform {
+    /// Samples the waveform at a phase (in radians). Ranges from -1.0 to 1.0.
+    pub fn sample(&self, a: f32) -> f32 {
+        match self {
+            Waveform::Sine => a.sin(),
+            Waveform::Triangle => a.sin().asin() * 2.0 / PI,
+            Waveform::Square => {
+                if a.sin() >= 0.0 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sawtooth => (a / (2.0 * PI) + 0.5).rem_euclid(1.0) * 2.0 - 1.0,
+        }
+    }
+}
+
+/// How the phase of each row's wave starts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WavePhase {
+    /// Every row starts at a phase of zero.
+    Zero,
+    /// Every row starts at a random phase.
+    Random,
+    /// Every row starts at the phase the previous row ended at.
+    Continuous,
+}
+
 #[derive(Clone)]
 pub struct WaveSketcherSettings {
     /// What stroke to create the lines with.
     pub stroke: Stroke,
+    /// If set, colors each row by its position, from the first row (`0.0`) to the last (`1.0`), instead of the stroke color.
+    pub stroke_colors: Option<ColorMap>,
     /// Only draw every __ rows of pixels.
     pub skip_rows: usize,
     /// Only draw every __ columns of pixels.
@@ -24,114 +69,191 @@ pub struct WaveSketcherSettings {
     /// Radius of a box blur applied to the input image. For no box blur, set this to zero.
     pub box_blur_radius: usize,
     /// Modulate stroke with frequency.
+    /// Each line is then drawn as a filled outline rather than a stroke.
     pub stroke_with_frequency: bool,
+    /// The shape of the waves.
+    pub waveform: Waveform,
+    /// How the phase of each row starts.
+    pub phase: WavePhase,
+    /// The direction the rows run in, in radians counter-clockwise from horizontal.
+    pub angle: f32,
 }
 
 pub struct WaveSketcher {
     settings: WaveSketcherSettings,
     input_image: RasterCanvas,
     canvas: VectorCanvas,
+    #[cfg(feature = "small-rng")]
+    rng: SmallRng,
 }
 
 impl WaveSketcher {
-    pub fn new(input_image: RasterCanvas, settings: WaveSketcherSettings) -> Self {
+    pub fn new(
+        input_image: RasterCanvas,
+        settings: WaveSketcherSettings,
+        #[cfg(feature = "small-rng")] seed: u64,
+    ) -> Self {
         Self {
             settings,
             input_image,
             canvas: VectorCanvas::default(),
+            #[cfg(feature = "small-rng")]
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Gets the brightness of the input image at a point in pixel space, accounting for [invert_brightness](WaveSketcherSettings::invert_brightness).
+    /// Returns [None] if the point is outside of the image.
+    fn brightness(&self, p: Vec2) -> Option<f32> {
+        if p.x < 0.0
+            || p.y < 0.0
+            || p.x >= self.input_image.width() as f32
+            || p.y >= self.input_image.height() as f32
+        {
+            return None;
+        }
+
+        let brightness = self.input_image.get_blurred_brightness(
+            p.x as usize,
+            p.y as usize,
+            self.settings.box_blur_radius,
+        );
+
+        if self.settings.invert_brightness {
+            Some(1.0 - brightness)
+        } else {
+            Some(brightness)
+        }
+    }
+
+    /// Picks a random phase for the start of a row.
+    fn random_phase(&mut self) -> f32 {
+        #[cfg(feature = "thread-rng")]
+        let mut rng = rand::thread_rng();
+        #[cfg(feature = "small-rng")]
+        let rng = &mut self.rng;
+
+        rng.gen_range(0.0..(2.0 * PI))
+    }
+
+    /// Draws a line (if it is long enough), then clears it to start a new one.
+    /// If [stroke_with_frequency](WaveSketcherSettings::stroke_with_frequency) is set,
+    /// the line is drawn as a single filled outline, with its width varying along its length.
+    fn draw_line(&mut self, line_points: &mut Vec<Vec2>, line_widths: &mut Vec<f32>, t: f32) {
+        let stroke = map_stroke(
+            self.settings.stroke,
+            self.settings.stroke_colors.as_ref(),
+            t,
+        );
+
+        if line_points.len() >= 2 {
+            if self.settings.stroke_with_frequency {
+                let outline = variable_width_outline(
+                    line_points,
+                    line_widths,
+                    stroke.line_end == LineEnd::Round,
+                );
+
+                if !outline.is_empty() {
+                    self.canvas.draw_shape(outline, None, Some(stroke.color));
+                }
+
+                line_points.clear();
+            } else {
+                self.canvas
+                    .draw_shape(std::mem::take(line_points), Some(stroke), None);
+            }
+        } else {
+            line_points.clear();
         }
+
+        line_widths.clear();
     }
 
     fn run<P: Fn(f32)>(&mut self, before_iter: P) {
-        let major_dimension = usize::min(self.input_image.width(), self.input_image.height()) as f32;
+        let transform = PixelTransform::new(self.input_image.width(), self.input_image.height());
 
-        let half_size = Vec2::new(
+        let size = Vec2::new(
             self.input_image.width() as f32,
             self.input_image.height() as f32,
-        ) / 2.0;
-
-        let scale_mat = Mat2::from_cols(
-            Vec2::X * (2.0 / major_dimension as f32),
-            Vec2::Y * (-2.0 / major_dimension as f32),
         );
+        let center = size / 2.0;
 
-        // Create a line for each row of pixels, skipping the necessary number.
-        let mut row = 0;
-        while row < self.input_image.height() {
-            before_iter(row as f32 / self.input_image.height() as f32);
-
-            // TODO: Add option to randomize phase
-            let mut a = 0.0;
-            let mut line_points = Vec::new();
+        // Rows run along `direction` and are stacked along `normal`.
+        // Pixel space has y pointing down, so the angle is flipped to be counter-clockwise on screen.
+        let direction = Vec2::new(self.settings.angle.cos(), -self.settings.angle.sin());
+        let normal = direction.perp();
 
-            let mut column = 0;
-            while column < self.input_image.width() {
-                let mut blurred_brightness = 0.0;
+        // Find how far the rows must extend (and how many there must be) to cover the entire image.
+        let corners = [
+            -center,
+            Vec2::new(center.x, -center.y),
+            Vec2::new(-center.x, center.y),
+            center,
+        ];
+        let row_start = corners
+            .iter()
+            .map(|c| c.dot(normal))
+            .fold(f32::INFINITY, f32::min);
+        let row_end = corners
+            .iter()
+            .map(|c| c.dot(normal))
+            .fold(f32::NEG_INFINITY, f32::max);
+        let column_start = corners
+            .iter()
+            .map(|c| c.dot(direction))
+            .fold(f32::INFINITY, f32::min);
+        let column_end = corners
+            .iter()
+            .map(|c| c.dot(direction))
+            .fold(f32::NEG_INFINITY, f32::max);
 
-                if self.settings.box_blur_radius == 0 {
-                    let pixel = self.input_image.get_pixel(column, row);
+        let row_step = 1.0 + self.settings.skip_rows as f32;
+        let column_step = 1.0 + self.settings.skip_columns as f32;
 
-                    blurred_brightness = (pixel.r() + pixel.g() + pixel.b()) * pixel.a() / 3.0;
-                } else {
-                    for x in column - self.settings.box_blur_radius
-                        ..column + self.settings.box_blur_radius
-                    {
-                        for y in
-                            row - self.settings.box_blur_radius..row + self.settings.box_blur_radius
-                        {
-                            let pixel = self.input_image.get_pixel(x, y);
-
-                            blurred_brightness +=
-                                (pixel.r() + pixel.g() + pixel.b()) * pixel.a() / 3.0
-                        }
-                    }
+        let mut a = 0.0;
 
-                    let k = self.settings.box_blur_radius as f32 * 2.0;
+        // Create a line for each row of pixels, skipping the necessary number.
+        let mut row = row_start;
+        while row < row_end {
+            let progress = (row - row_start) / (row_end - row_start);
+            before_iter(progress);
 
-                    blurred_brightness /= k * k;
-                }
+            match self.settings.phase {
+                WavePhase::Zero => a = 0.0,
+                WavePhase::Random => a = self.random_phase(),
+                WavePhase::Continuous => (),
+            }
 
-                if blurred_brightness >= self.settings.brightness_threshold {
-                    let delta_a = blurred_brightness * self.settings.frequency_multiplier;
+            let mut line_points = Vec::new();
+            let mut line_widths = Vec::new();
 
-                    a += delta_a * (self.settings.skip_columns as f32 + 1.0);
-                    a %= 2.0 * PI;
+            let mut column = column_start;
+            while column < column_end {
+                let sample = center + direction * column + normal * row;
 
-                    let y = a.sin() * self.settings.amplitude_multiplier;
+                match self.brightness(sample) {
+                    Some(brightness) if brightness >= self.settings.brightness_threshold => {
+                        let delta_a = brightness * self.settings.frequency_multiplier;
 
-                    let p = scale_mat.mul_vec2(Vec2::new(column as f32, y + row as f32) - half_size);
+                        a += delta_a * column_step;
+                        a %= 2.0 * PI;
 
-                    line_points.push(p);
+                        let y =
+                            self.settings.waveform.sample(a) * self.settings.amplitude_multiplier;
 
-                    if self.settings.stroke_with_frequency && line_points.len() >= 2 {
-                        self.canvas.draw_shape(
-                            line_points,
-                            Some(Stroke {
-                                color: self.settings.stroke.color,
-                                width: self.settings.stroke.width * delta_a * 10.0,
-                                line_end: self.settings.stroke.line_end,
-                            }),
-                            None,
-                        );
-                        line_points = vec![p];
+                        line_points.push(transform.apply(sample + normal * y));
+                        line_widths.push(self.settings.stroke.width * delta_a * 10.0);
                     }
-                } else if line_points.len() >= 2 {
-                    self.canvas
-                        .draw_shape(line_points, Some(self.settings.stroke), None);
-                    line_points = Vec::new();
-                } else if !line_points.is_empty() {
-                    line_points = Vec::new();
+                    _ => self.draw_line(&mut line_points, &mut line_widths, progress),
                 }
 
-                column += 1 + self.settings.skip_columns;
+                column += column_step;
             }
 
-            if line_points.len() >= 2 {
-                self.canvas
-                    .draw_shape(line_points, Some(self.settings.stroke), None);
-            }
+            self.draw_line(&mut line_points, &mut line_widths, progress);
 
-            row += 1 + self.settings.skip_rows;
+            row += row_step;
         }
     }
 }
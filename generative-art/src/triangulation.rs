@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+
+use barium::Vec2;
+
+/// Computes the Delaunay triangulation of a set of points, using the Bowyer-Watson algorithm.
+/// Returns the indices of the corners of each triangle, in counter-clockwise order (with y pointing up).
+///
+/// Duplicate points are ignored, as are triangles with no area.
+pub(crate) fn delaunay(points: &[Vec2]) -> Vec<[usize; 3]> {
+    if points.len() < 3 {
+        return Vec::new();
+    }
+
+    let min = points
+        .iter()
+        .fold(Vec2::splat(f32::INFINITY), |a, &b| a.min(b));
+    let max = points
+        .iter()
+        .fold(Vec2::splat(f32::NEG_INFINITY), |a, &b| a.max(b));
+
+    let center = (min + max) / 2.0;
+    let extent = (max - min).max_element().max(1.0) * 20.0;
+
+    // A triangle large enough to contain every point, which is removed at the end.
+    let mut vertices = points.to_vec();
+    let super_start = vertices.len();
+    vertices.push(center + Vec2::new(-extent, -extent));
+    vertices.push(center + Vec2::new(extent, -extent));
+    vertices.push(center + Vec2::new(0.0, extent));
+
+    let mut triangles = vec![Triangle::new(
+        [super_start, super_start + 1, super_start + 2],
+        &vertices,
+    )];
+
+    let mut inserted = HashSet::with_capacity(points.len());
+    let mut boundary = Vec::new();
+
+    for (i, &p) in points.iter().enumerate() {
+        if !inserted.insert((p.x.to_bits(), p.y.to_bits())) {
+            continue;
+        }
+
+        // Find every triangle whose circumcircle contains the new point, then remove them, leaving a hole.
+        boundary.clear();
+
+        triangles.retain(|triangle: &Triangle| {
+            if !triangle.circumcircle_contains(p) {
+                return true;
+            }
+
+            for edge in triangle.edges() {
+                // Edges shared by two removed triangles are inside the hole, not on its boundary.
+                if let Some(shared) = boundary
+                    .iter()
+                    .position(|&(a, b)| (a, b) == (edge.1, edge.0))
+                {
+                    boundary.swap_remove(shared);
+                } else {
+                    boundary.push(edge);
+                }
+            }
+
+            false
+        });
+
+        // Fill the hole by connecting its boundary to the new point.
+        for &(a, b) in &boundary {
+            triangles.push(Triangle::new([a, b, i], &vertices));
+        }
+    }
+
+    triangles
+        .into_iter()
+        .filter(|triangle| triangle.radius_squared.is_finite())
+        .map(|triangle| triangle.corners)
+        .filter(|corners| corners.iter().all(|&c| c < super_start))
+        .collect()
+}
+
+/// Computes the Voronoi cell of each point, clipped to a rectangle.
+/// Cells are returned in the same order as the points, and are empty for duplicate points.
+pub(crate) fn voronoi_cells(points: &[Vec2], min: Vec2, max: Vec2) -> Vec<Vec<Vec2>> {
+    let bounds = vec![min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+
+    // Only Delaunay neighbours share edges of a Voronoi cell, so those are the only half-planes that need clipping.
+    let mut neighbours = vec![Vec::new(); points.len()];
+
+    let triangles = delaunay(points);
+
+    if triangles.is_empty() {
+        // Without a triangulation (too few points, or all of them in a line), compare every pair instead.
+        for (i, neighbours) in neighbours.iter_mut().enumerate() {
+            neighbours.extend((0..points.len()).filter(|&j| points[j] != points[i]));
+        }
+    } else {
+        for [a, b, c] in triangles {
+            for (from, to) in [(a, b), (b, c), (c, a), (b, a), (c, b), (a, c)] {
+                if !neighbours[from].contains(&to) {
+                    neighbours[from].push(to);
+                }
+            }
+        }
+    }
+
+    points
+        .iter()
+        .zip(neighbours)
+        .enumerate()
+        .map(|(i, (&site, neighbours))| {
+            // Duplicate points are left out of the triangulation, so they have no cell.
+            if neighbours.is_empty() && points.iter().position(|&p| p == site) != Some(i) {
+                return Vec::new();
+            }
+
+            neighbours.iter().fold(bounds.clone(), |cell, &other| {
+                clip_half_plane(&cell, site, points[other])
+            })
+        })
+        .collect()
+}
+
+/// Clips a convex polygon to the half of the plane closer to `site` than to `other`.
+fn clip_half_plane(polygon: &[Vec2], site: Vec2, other: Vec2) -> Vec<Vec2> {
+    let normal = other - site;
+    let midpoint = (site + other) / 2.0;
+
+    let distance = |p: Vec2| (p - midpoint).dot(normal);
+
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+
+        let da = distance(a);
+        let db = distance(b);
+
+        if da <= 0.0 {
+            clipped.push(a);
+        }
+
+        if (da <= 0.0) != (db <= 0.0) {
+            clipped.push(a + (b - a) * (da / (da - db)));
+        }
+    }
+
+    clipped
+}
+
+/// The area-weighted centroid of a polygon.
+pub(crate) fn polygon_centroid(polygon: &[Vec2]) -> Option<Vec2> {
+    let mut area = 0.0;
+    let mut centroid = Vec2::ZERO;
+
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        let cross = a.perp_dot(b);
+
+        area += cross;
+        centroid += (a + b) * cross;
+    }
+
+    if area.abs() <= f32::EPSILON {
+        None
+    } else {
+        Some(centroid / (3.0 * area))
+    }
+}
+
+struct Triangle {
+    corners: [usize; 3],
+    circumcenter: Vec2,
+    radius_squared: f32,
+}
+
+impl Triangle {
+    fn new(mut corners: [usize; 3], vertices: &[Vec2]) -> Self {
+        let [a, b, c] = corners.map(|i| vertices[i]);
+
+        // Keep a consistent winding, so shared edges can be matched up.
+        if (b - a).perp_dot(c - a) < 0.0 {
+            corners.swap(1, 2);
+        }
+
+        // Work relative to the first corner, to keep precision with the large super triangle.
+        let (b, c) = (b - a, c - a);
+        let d = 2.0 * b.perp_dot(c);
+
+        if d.abs() <= f32::EPSILON {
+            // Degenerate triangles contain every point, so they are removed as soon as possible.
+            return Self {
+                corners,
+                circumcenter: a,
+                radius_squared: f32::INFINITY,
+            };
+        }
+
+        let (b2, c2) = (b.length_squared(), c.length_squared());
+        let offset = Vec2::new(c.y * b2 - b.y * c2, b.x * c2 - c.x * b2) / d;
+
+        let circumcenter = a + offset;
+        let radius_squared = offset.length_squared();
+
+        Self {
+            corners,
+            circumcenter,
+            radius_squared,
+        }
+    }
+
+    fn circumcircle_contains(&self, p: Vec2) -> bool {
+        self.circumcenter.distance_squared(p) <= self.radius_squared
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.corners;
+
+        [(a, b), (b, c), (c, a)]
+    }
+}